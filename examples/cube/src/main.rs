@@ -1,4 +1,6 @@
 use term3d::*;
+use term3d::console::ArgType;
+use term3d::controller::FpsController;
 
 const COLORS: &[Color] = &[
     Color::Red,   // face 1
@@ -9,12 +11,17 @@ const COLORS: &[Color] = &[
     Color::Magenta,
 ];
 
-struct App;
+struct App {
+    controller: FpsController,
+}
 
 impl Game for App {
     fn start(&mut self, term: &mut Term3D) {
+        // Smooth movement in terminals which report key releases
+        term.set_kitty_keyboard(true);
+
         // Initialize camera
-        term.cam.transform = Transform {
+        term.cameras[0].transform = Transform {
             pos: (6., -2., -10.),
             rot: (0.15, -0.5),
         };
@@ -28,57 +35,32 @@ impl Game for App {
         // Create an object with the cube mesh
         let obj = Object::new(cube);
         // Add the object to the scene
-        term.objects.push(obj);
+        term.objects.spawn(obj);
+
+        // Commands for the console, opened with the backtick key
+        term.console.register("tp", &[ArgType::Float, ArgType::Float, ArgType::Float], "move the camera to x y z");
+        term.console.register("spawn", &[ArgType::Float, ArgType::Float, ArgType::Float], "add a cube at x y z");
     }
 
-    fn update(&mut self, term: &mut Term3D, delta: f32, key: Option<Input>) {
+    fn update(&mut self, term: &mut Term3D, delta: f32) {
         // Print framerate at top-middle of screen
         // term.backend.move_rc(0, term.backend.get_row_col_count().1 / 2);
         // term.backend.set_color_pair(ColorPair::new(Color::Yellow, Color::Black));
         // term.backend.print("20");
         //term.log(&format!("{}", (1./delta) as u32), Color::Yellow);
 
-        if let Some(input) = key {
-            let s = delta * 10.;
-            let cam = &mut term.cam;
+        // WASD moves, Q and E go down and up, and the arrow keys look around
+        self.controller.update(&mut term.cameras[0], &term.input, delta);
 
-            match input {
-                Input::Character('q') => cam.transform.pos.1 += s, // Go down
-                Input::Character('e') => cam.transform.pos.1 -= s, // Go up
-
-                Input::Character('w')
-                | Input::Character('a')
-                | Input::Character('s')
-                | Input::Character('d') => {
-                    let (x, y) = (s * cam.transform.rot.1.sin(), s * cam.transform.rot.1.cos());
-                    match input {
-                        Input::Character('w') => { // Forward
-                            cam.transform.pos.0 += x;
-                            cam.transform.pos.2 += y;
-                        }
-                        Input::Character('s') => { // Backward 
-                            cam.transform.pos.0 -= x;
-                            cam.transform.pos.2 -= y;
-                        }
-                        Input::Character('a') => { // Left
-                            cam.transform.pos.0 -= y;
-                            cam.transform.pos.2 += x;
-                        }
-                        Input::Character('d') => { // Right
-                            cam.transform.pos.0 += y;
-                            cam.transform.pos.2 -= x;
-                        }
-                        _ => unreachable!(),
-                    }
+        while let Some(command) = term.console.next_command() {
+            let pos = [command.float(0), command.float(1), command.float(2)];
+            match command.name.as_str() {
+                "tp" => term.cameras[0].set_world_position(pos),
+                "spawn" => {
+                    let mut obj = Object::new(Mesh::cube());
+                    obj.transform.pos = (pos[0], pos[1], pos[2]);
+                    term.objects.spawn(obj);
                 }
-
-                // The following inputs are for the arrow keys,
-                // which in this example control looking around.
-                Input::KeyUp => cam.transform.rot.0 -= s,
-                Input::KeyDown => cam.transform.rot.0 += s,
-                Input::KeyLeft => cam.transform.rot.1 -= s,
-                Input::KeyRight => cam.transform.rot.1 += s,
-
                 _ => {}
             }
         }
@@ -87,5 +69,7 @@ impl Game for App {
 
 fn main() {
     let mut term3d = Term3D::new();
-    term3d.run(&mut App);
+    term3d.run(&mut App {
+        controller: FpsController::new(),
+    });
 }
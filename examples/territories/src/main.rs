@@ -47,9 +47,9 @@ impl Game for App {
         self.map_create_spawn(spawn_origin, self.factions[&self.player_fac_id].color);
     }
 
-    fn update(&mut self, term: &mut Term3D, delta: f32, key: Option<Input>) {
+    fn update(&mut self, term: &mut Term3D, delta: f32) {
         // Update direction
-        if let Some(input) = key {
+        for &input in term.input.events() {
             match input {
                 Input::KeyRight => self.player_pos.0 = min(WORLD_WIDTH as i32, self.player_pos.0 + 1), // Go right
                 Input::KeyLeft => self.player_pos.0 = max(0, self.player_pos.0 - 1), // Go left
@@ -67,7 +67,9 @@ impl Game for App {
             let (h, w) = term.backend.get_row_col_count();
             (self.viewing_bounds.0, min(WORLD_WIDTH as i32 - 1, w - 1), self.viewing_bounds.2, min(WORLD_HEIGHT as i32 - 1, h - 1))
         };
+    }
 
+    fn draw_overlay(&mut self, term: &mut Term3D) {
         // Draw the map (index only map tiles within view bounds)
         for x in self.viewing_bounds.0..self.viewing_bounds.1 {
             for y in self.viewing_bounds.2..self.viewing_bounds.3 {
@@ -0,0 +1,594 @@
+//! Named actions and axes bound to keys, so games don't hard-code their controls.
+//!
+//! Games define default bindings in code, which can then be overridden from a
+//! config file in a small subset of TOML, and rebound while running:
+//!
+//! ```toml
+//! # Each action is held while any of its keys are
+//! [actions]
+//! move_forward = ["w", "Up"]
+//! jump = ["Space"]
+//!
+//! # Each axis is the sum of the values of its held keys, from -1 to 1
+//! [axes.look_x]
+//! Right = 1.0
+//! Left = -1.0
+//! ```
+//!
+//! Keys are named by the character they type, or by one of the names listed in
+//! `key_name`.
+
+use crate::input::InputState;
+use crate::Term3D;
+
+use easycurses::Input;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub struct ActionMap {
+    actions: HashMap<String, Vec<Input>>,
+    axes: HashMap<String, Vec<(Input, f32)>>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self {
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    /// Add a key to an action, alongside any keys already bound to it.
+    pub fn bind(&mut self, action: &str, key: Input) {
+        let keys = self.actions.entry(action.to_owned()).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    /// Replace every key bound to an action.
+    pub fn rebind(&mut self, action: &str, keys: Vec<Input>) {
+        self.actions.insert(action.to_owned(), keys);
+    }
+
+    /// Add a key to an axis, which pushes it by `value` while held.
+    pub fn bind_axis(&mut self, axis: &str, key: Input, value: f32) {
+        let keys = self.axes.entry(axis.to_owned()).or_default();
+        keys.retain(|&(k, _)| k != key);
+        keys.push((key, value));
+    }
+
+    /// Replace every key bound to an axis.
+    pub fn rebind_axis(&mut self, axis: &str, keys: Vec<(Input, f32)>) {
+        self.axes.insert(axis.to_owned(), keys);
+    }
+
+    /// The keys bound to an action.
+    pub fn bindings(&self, action: &str) -> &[Input] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The keys bound to an axis, and the value each gives it.
+    pub fn axis_bindings(&self, axis: &str) -> &[(Input, f32)] {
+        self.axes.get(axis).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any of an action's keys are held.
+    pub fn is_down(&self, input: &InputState, action: &str) -> bool {
+        self.bindings(action).iter().any(|key| input.is_down(key))
+    }
+
+    /// Whether one of an action's keys was pressed this frame, when none were held before.
+    pub fn just_pressed(&self, input: &InputState, action: &str) -> bool {
+        let keys = self.bindings(action);
+        keys.iter().any(|key| input.just_pressed(key))
+            && keys.iter().all(|key| input.just_pressed(key) || !input.is_down(key))
+    }
+
+    /// Whether the last of an action's held keys was released this frame.
+    pub fn just_released(&self, input: &InputState, action: &str) -> bool {
+        let keys = self.bindings(action);
+        keys.iter().any(|key| input.just_released(key)) && !keys.iter().any(|key| input.is_down(key))
+    }
+
+    /// The sum of the values of an axis's held keys, clamped between -1 and 1.
+    pub fn axis(&self, input: &InputState, axis: &str) -> f32 {
+        self.axis_bindings(axis)
+            .iter()
+            .filter(|(key, _)| input.is_down(key))
+            .map(|(_, value)| value)
+            .sum::<f32>()
+            .clamp(-1., 1.)
+    }
+
+    /// Override bindings with those in a config file. Actions and axes which aren't in
+    /// the file keep their current bindings.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ConfigError> {
+        self.parse(&fs::read_to_string(path)?)
+    }
+
+    /// Override bindings with those in config text. Nothing is changed if there's an error.
+    pub fn parse(&mut self, text: &str) -> Result<(), ConfigError> {
+        let mut actions = HashMap::new();
+        let mut axes = HashMap::<String, Vec<(Input, f32)>>::new();
+        let mut section = Section::None;
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: &str| ConfigError::Parse {
+                line: i + 1,
+                message: message.to_owned(),
+            };
+            let mut line = Line::new(line);
+            if line.at_end() {
+                continue;
+            }
+
+            if line.eat('[') {
+                let name = line.key().ok_or_else(|| error("expected a table name"))?;
+                section = if name == "actions" {
+                    Section::Actions
+                } else if name == "axes" && line.eat('.') {
+                    let axis = line.key().ok_or_else(|| error("expected an axis name"))?;
+                    axes.entry(axis.clone()).or_default();
+                    Section::Axis(axis)
+                } else {
+                    return Err(error("expected [actions] or [axes.<name>]"));
+                };
+                if !line.eat(']') || !line.at_end() {
+                    return Err(error("expected ']'"));
+                }
+                continue;
+            }
+
+            let name = line.key().ok_or_else(|| error("expected a name"))?;
+            if !line.eat('=') {
+                return Err(error("expected '='"));
+            }
+
+            match &section {
+                Section::None => return Err(error("expected a table header first")),
+                Section::Actions => {
+                    // An action's keys, as a string or an array of strings
+                    let mut names = Vec::new();
+                    if line.eat('[') {
+                        while !line.eat(']') {
+                            names.push(line.string().ok_or_else(|| error("expected a key name"))?);
+                            if !line.eat(',') && !line.rest.starts_with(']') {
+                                return Err(error("expected ',' or ']'"));
+                            }
+                        }
+                    } else {
+                        names.push(line.string().ok_or_else(|| error("expected a key name"))?);
+                    }
+
+                    let mut keys = Vec::new();
+                    for name in names {
+                        keys.push(parse_key(&name).ok_or_else(|| error(&format!("unknown key {:?}", name)))?);
+                    }
+                    actions.insert(name, keys);
+                }
+                Section::Axis(axis) => {
+                    let key = parse_key(&name).ok_or_else(|| error(&format!("unknown key {:?}", name)))?;
+                    let value = line.number().ok_or_else(|| error("expected a number"))?;
+                    axes.get_mut(axis).unwrap().push((key, value));
+                }
+            }
+
+            if !line.at_end() {
+                return Err(error("unexpected text after value"));
+            }
+        }
+
+        self.actions.extend(actions);
+        self.axes.extend(axes);
+        Ok(())
+    }
+
+    /// Write every binding to a config file, which `load` can read back.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_config())
+    }
+
+    /// Every binding as config text, which `parse` can read back.
+    pub fn to_config(&self) -> String {
+        let mut actions = self.actions.iter().collect::<Vec<_>>();
+        actions.sort_by(|a, b| a.0.cmp(b.0));
+        let mut out = String::from("[actions]\n");
+        for (action, keys) in actions {
+            let keys = keys.iter().map(|&key| quote(&key_name(key))).collect::<Vec<_>>();
+            out += &format!("{} = [{}]\n", key(action), keys.join(", "));
+        }
+
+        let mut axes = self.axes.iter().collect::<Vec<_>>();
+        axes.sort_by(|a, b| a.0.cmp(b.0));
+        for (axis, keys) in axes {
+            out += &format!("\n[axes.{}]\n", key(axis));
+            for &(name, value) in keys {
+                out += &format!("{} = {:?}\n", key(&key_name(name)), value);
+            }
+        }
+        out
+    }
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Term3D {
+    /// Whether any of an action's keys are held. See `ActionMap::is_down`.
+    pub fn action_down(&self, action: &str) -> bool {
+        self.actions.is_down(&self.input, action)
+    }
+
+    /// See `ActionMap::just_pressed`.
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.actions.just_pressed(&self.input, action)
+    }
+
+    /// See `ActionMap::just_released`.
+    pub fn action_released(&self, action: &str) -> bool {
+        self.actions.just_released(&self.input, action)
+    }
+
+    /// The value of an axis from -1 to 1. See `ActionMap::axis`.
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.actions.axis(&self.input, axis)
+    }
+}
+
+/// The table of a config file being read.
+enum Section {
+    None,
+    Actions,
+    Axis(String),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+const KEY_NAMES: &[(Input, &str)] = &[
+    (Input::Character(' '), "Space"),
+    (Input::Character('\t'), "Tab"),
+    (Input::Character('\n'), "Enter"),
+    (Input::Character('\u{1b}'), "Escape"),
+    (Input::KeyBackspace, "Backspace"),
+    (Input::KeyEnter, "KeypadEnter"),
+    (Input::KeyUp, "Up"),
+    (Input::KeyDown, "Down"),
+    (Input::KeyLeft, "Left"),
+    (Input::KeyRight, "Right"),
+    (Input::KeyHome, "Home"),
+    (Input::KeyEnd, "End"),
+    (Input::KeyPPage, "PageUp"),
+    (Input::KeyNPage, "PageDown"),
+    (Input::KeyIC, "Insert"),
+    (Input::KeyDC, "Delete"),
+    (Input::KeyF1, "F1"),
+    (Input::KeyF2, "F2"),
+    (Input::KeyF3, "F3"),
+    (Input::KeyF4, "F4"),
+    (Input::KeyF5, "F5"),
+    (Input::KeyF6, "F6"),
+    (Input::KeyF7, "F7"),
+    (Input::KeyF8, "F8"),
+    (Input::KeyF9, "F9"),
+    (Input::KeyF10, "F10"),
+    (Input::KeyF11, "F11"),
+    (Input::KeyF12, "F12"),
+];
+
+/// Keys missing from pancurses' `SPECIAL_KEY_CODES` on some platforms.
+const OTHER_KEYS: &[Input] = &[
+    Input::KeyAbort,
+    Input::KeyLHelp,
+    Input::KeyResize,
+    Input::KeyEvent,
+    Input::KeyMouse,
+    Input::KeyA1,
+    Input::KeyA3,
+    Input::KeyB2,
+    Input::KeyC1,
+    Input::KeyC3,
+];
+
+/// The name a key is written as in config files: its character, or one of Space, Tab,
+/// Enter, Escape, Backspace, KeypadEnter, Up, Down, Left, Right, Home, End, PageUp,
+/// PageDown, Insert, Delete, or F1 to F12. Other control characters are written as
+/// their code point, like `U+007F`, and other keys in their `Debug` form, like
+/// `KeyF13`. Every key has its own name, which `parse_key` reads back.
+pub fn key_name(key: Input) -> String {
+    match KEY_NAMES.iter().find(|(k, _)| *k == key) {
+        Some((_, name)) => (*name).to_owned(),
+        None => match key {
+            Input::Character(c) if c.is_control() => format!("U+{:04X}", c as u32),
+            Input::Character(c) => c.to_string(),
+            key => format!("{:?}", key),
+        },
+    }
+}
+
+/// The key with the given name, as written by `key_name`.
+pub fn parse_key(name: &str) -> Option<Input> {
+    if let Some(&(key, _)) = KEY_NAMES.iter().find(|(_, n)| *n == name) {
+        return Some(key);
+    }
+    if let Some(hex) = name.strip_prefix("U+") {
+        return u32::from_str_radix(hex, 16).ok().and_then(std::char::from_u32).map(Input::Character);
+    }
+    if let Some(code) = name.strip_prefix("Unknown(").and_then(|rest| rest.strip_suffix(')')) {
+        return code.parse().ok().map(Input::Unknown);
+    }
+    let mut special = pancurses::SPECIAL_KEY_CODES.iter().chain(OTHER_KEYS);
+    if let Some(&key) = special.find(|key| format!("{:?}", key) == name) {
+        return Some(key);
+    }
+
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Input::Character(c)),
+        _ => None,
+    }
+}
+
+/// A name for the left of `=` or in a table header, bare if it can be.
+fn key(s: &str) -> String {
+    let bare = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if !s.is_empty() && s.chars().all(bare) {
+        s.to_owned()
+    } else {
+        quote(s)
+    }
+}
+
+/// A string in double quotes, with quotes, backslashes and control characters escaped.
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\t' => out += "\\t",
+            c if c.is_control() => out += &format!("\\u{:04X}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Reads the tokens of one line of config text.
+struct Line<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn new(line: &'a str) -> Self {
+        let mut line = Self { rest: line };
+        line.skip_space();
+        line
+    }
+
+    fn skip_space(&mut self) {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('#') {
+            self.rest = "";
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    /// Take a character if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        if self.rest.starts_with(c) {
+            self.rest = &self.rest[c.len_utf8()..];
+            self.skip_space();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// A bare or quoted key.
+    fn key(&mut self) -> Option<String> {
+        if self.rest.starts_with('"') {
+            return self.string();
+        }
+
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let key = self.rest[..end].to_owned();
+        self.rest = &self.rest[end..];
+        self.skip_space();
+        Some(key)
+    }
+
+    /// A double quoted string, with backslash escapes.
+    fn string(&mut self) -> Option<String> {
+        let mut chars = self.rest.strip_prefix('"')?.char_indices();
+        let mut out = String::new();
+        loop {
+            match chars.next()? {
+                (i, '"') => {
+                    self.rest = &self.rest[i + 2..];
+                    self.skip_space();
+                    return Some(out);
+                }
+                (_, '\\') => out.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    'u' => {
+                        let hex = (0..4).map(|_| chars.next().map(|(_, c)| c)).collect::<Option<String>>()?;
+                        std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    c => c,
+                }),
+                (_, c) => out.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE_".contains(c)))
+            .unwrap_or(self.rest.len());
+        let value = self.rest[..end].replace('_', "").parse().ok()?;
+        self.rest = &self.rest[end..];
+        self.skip_space();
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> (usize, String) {
+        match ActionMap::new().parse(text) {
+            Err(ConfigError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parses_actions_and_axes() {
+        let mut map = ActionMap::new();
+        map.bind("jump", Input::Character('x'));
+        map.bind("fire", Input::Character('f'));
+        let text = r#"
+            # Comments and blank lines are skipped
+            [actions]
+            jump = ["Space", "w"]  # replaces the default
+            "open map" = "\u0041"
+            menu = []
+
+            [axes.look_x]
+            Right = 1.0
+            Left = -1
+            "," = 0.5
+        "#;
+        map.parse(text).unwrap();
+
+        assert_eq!(map.bindings("jump"), &[Input::Character(' '), Input::Character('w')]);
+        assert_eq!(map.bindings("fire"), &[Input::Character('f')]);
+        assert_eq!(map.bindings("open map"), &[Input::Character('A')]);
+        assert!(map.bindings("menu").is_empty());
+        assert_eq!(
+            map.axis_bindings("look_x"),
+            &[(Input::KeyRight, 1.), (Input::KeyLeft, -1.), (Input::Character(','), 0.5)]
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let cases: &[(&str, usize, &str)] = &[
+            ("jump = \"w\"", 1, "expected a table header first"),
+            ("[actions]\n\n# comment\njump = [\"Nope\"]", 4, "unknown key \"Nope\""),
+            ("[actions]\njump = [\"w\"", 2, "expected ',' or ']'"),
+            ("[actions]\njump = [\"w\" \"s\"]", 2, "expected ',' or ']'"),
+            ("[actions]\njump = \"w\" extra", 2, "unexpected text after value"),
+            ("[actions]\njump \"w\"", 2, "expected '='"),
+            ("[actions]\n= \"w\"", 2, "expected a name"),
+            ("[actions]\njump = w", 2, "expected a key name"),
+            ("[actions]\njump = \"w", 2, "expected a key name"),
+            ("[bindings]", 1, "expected [actions] or [axes.<name>]"),
+            ("[axes.]", 1, "expected an axis name"),
+            ("[actions", 1, "expected ']'"),
+            ("[]", 1, "expected a table name"),
+            ("[axes.x]\nLeft = fast", 2, "expected a number"),
+            ("[axes.x]\nNope = 1.0", 2, "unknown key \"Nope\""),
+        ];
+        for &(text, line, message) in cases {
+            assert_eq!(parse_error(text), (line, message.to_owned()), "parsing {:?}", text);
+        }
+    }
+
+    #[test]
+    fn errors_change_nothing() {
+        let mut map = ActionMap::new();
+        map.bind("jump", Input::Character(' '));
+        assert!(map.parse("[actions]\njump = [\"w\"]\nfire = [\"Nope\"]").is_err());
+        assert_eq!(map.bindings("jump"), &[Input::Character(' ')]);
+        assert!(map.bindings("fire").is_empty());
+    }
+
+    #[test]
+    fn every_key_name_reads_back() {
+        let mut keys = pancurses::SPECIAL_KEY_CODES.to_vec();
+        keys.extend(OTHER_KEYS);
+        keys.extend(KEY_NAMES.iter().map(|&(key, _)| key));
+        keys.extend((0..0x20).chain(0x7f..0xa0).filter_map(std::char::from_u32).map(Input::Character));
+        keys.extend("aZ9 ,#\"\\=[]U+é".chars().map(Input::Character));
+        keys.extend(&[Input::Unknown(57441), Input::Unknown(-1)]);
+
+        let mut names = HashMap::new();
+        for &key in &keys {
+            let name = key_name(key);
+            assert_eq!(parse_key(&name), Some(key), "{:?} is named {:?}", key, name);
+            if let Some(other) = names.insert(name.clone(), key) {
+                assert_eq!(other, key, "{:?} and {:?} are both named {:?}", other, key, name);
+            }
+        }
+        assert_eq!(parse_key("Nope"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn saved_config_loads_back() {
+        let mut map = ActionMap::new();
+        map.rebind(
+            "menu",
+            vec![Input::KeyEnter, Input::Character('\n'), Input::Character('\u{7f}'), Input::KeyBackspace],
+        );
+        map.rebind("odd keys", vec![Input::KeyF13, Input::KeyBTab, Input::Unknown(57441), Input::Character('"')]);
+        map.rebind("none", Vec::new());
+        map.rebind_axis("zoom", vec![(Input::Character('+'), 0.25), (Input::KeySF, -1.), (Input::Character('\t'), 1.)]);
+        map.rebind_axis("empty", Vec::new());
+
+        let mut loaded = ActionMap::new();
+        loaded.parse(&map.to_config()).unwrap();
+        for action in &["menu", "odd keys", "none"] {
+            assert_eq!(loaded.bindings(action), map.bindings(action), "action {}", action);
+        }
+        for axis in &["zoom", "empty"] {
+            assert_eq!(loaded.axis_bindings(axis), map.axis_bindings(axis), "axis {}", axis);
+        }
+        assert_eq!(loaded.to_config(), map.to_config());
+    }
+}
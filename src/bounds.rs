@@ -0,0 +1,112 @@
+//! Bounding volumes, used to skip work on geometry which can't be seen.
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// The smallest box containing every point. An empty list gives an empty box at the origin.
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        if points.is_empty() {
+            return Self {
+                min: [0.; 3],
+                max: [0.; 3],
+            };
+        }
+
+        let mut aabb = Self {
+            min: points[0],
+            max: points[0],
+        };
+        for p in &points[1..] {
+            for (k, &v) in p.iter().enumerate() {
+                aabb.min[k] = aabb.min[k].min(v);
+                aabb.max[k] = aabb.max[k].max(v);
+            }
+        }
+        aabb
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) / 2.,
+            (self.min[1] + self.max[1]) / 2.,
+            (self.min[2] + self.max[2]) / 2.,
+        ]
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// A sphere containing every point, centred on their bounding box.
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        let center = Aabb::from_points(points).center();
+        let radius = points
+            .iter()
+            .map(|p| (0..3).map(|k| (p[k] - center[k]).powi(2)).sum::<f32>())
+            .fold(0., f32::max)
+            .sqrt();
+        Self { center, radius }
+    }
+}
+
+/// Bounding volumes of a mesh, in the mesh's own space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: Sphere,
+}
+
+impl Bounds {
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        Self {
+            aabb: Aabb::from_points(points),
+            sphere: Sphere::from_points(points),
+        }
+    }
+}
+
+/// The volume of view space which is projected onto the screen.
+///
+/// View space is what `Camera::view` returns: the camera sits at the origin looking
+/// down +z, and a point `(x, y, z)` lands `scale * x / z` cells right of the centre of
+/// the screen and `scale * y / z` cells below it.
+pub struct Frustum {
+    /// Slope of the left and right planes, and the length of their (unnormalized) normals.
+    x: (f32, f32),
+    /// Slope of the top and bottom planes, and the length of their (unnormalized) normals.
+    y: (f32, f32),
+}
+
+impl Frustum {
+    /// The frustum of a `width` by `height` cell view with the given projection scale.
+    pub fn new(width: i32, height: i32, scale: f32) -> Self {
+        let kx = width as f32 / 2. / scale;
+        let ky = height as f32 / 2. / scale;
+        Self {
+            x: (kx, (1. + kx * kx).sqrt()),
+            y: (ky, (1. + ky * ky).sqrt()),
+        }
+    }
+
+    /// Whether any part of a sphere in view space may be visible.
+    pub fn intersects_sphere(&self, center: [f32; 3], radius: f32) -> bool {
+        let [x, y, z] = center;
+        let (kx, nx) = self.x;
+        let (ky, ny) = self.y;
+
+        z + radius > 0. // In front of the camera
+            && x - kx * z <= radius * nx // Right
+            && -x - kx * z <= radius * nx // Left
+            && y - ky * z <= radius * ny // Bottom
+            && -y - ky * z <= radius * ny // Top
+    }
+}
@@ -0,0 +1,561 @@
+//! A drop-down developer console, for typing commands into a running game.
+//!
+//! Games register their commands with the types of their arguments, then take the
+//! commands typed each frame:
+//!
+//! ```ignore
+//! term.console.register("tp", &[ArgType::Float, ArgType::Float, ArgType::Float], "move the camera");
+//!
+//! while let Some(command) = term.console.next_command() {
+//!     match command.name.as_str() {
+//!         "tp" => term.cameras[0].set_world_position([command.float(0), command.float(1), command.float(2)]),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+//!
+//! A few commands are built in, such as `set fps 30`; `help` lists them all. Output
+//! goes to `Term3D::log`, which the console shows above the line being typed.
+
+use crate::input::InputState;
+use crate::logging::{panel_rows, Log};
+use crate::Term3D;
+
+use easycurses::{Color, ColorPair, EasyCurses, Input};
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// The most lines kept in the history.
+const MAX_HISTORY: usize = 100;
+
+/// Variables the built-in `set` command can change.
+const VARIABLES: &[&str] = &["fps", "lifetime", "picking", "threads", "timestamps", "timestep"];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArgType {
+    Int,
+    /// A number, with or without a decimal point.
+    Float,
+    /// `true`, `false`, `on`, `off`, `1` or `0`.
+    Bool,
+    /// A word, or any text in double quotes.
+    Str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    Str(String),
+}
+
+impl ArgType {
+    fn parse(self, text: &str) -> Option<Value> {
+        match self {
+            ArgType::Int => text.parse().ok().map(Value::Int),
+            ArgType::Float => text.parse().ok().map(Value::Float),
+            ArgType::Bool => parse_bool(text).map(Value::Bool),
+            ArgType::Str => Some(Value::Str(text.to_owned())),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ArgType::Int => "int",
+            ArgType::Float => "float",
+            ArgType::Bool => "bool",
+            ArgType::Str => "text",
+        }
+    }
+}
+
+/// A command typed into the console, with its arguments already checked against
+/// the types it was registered with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub args: Vec<Value>,
+}
+
+impl Command {
+    /// An `ArgType::Int` argument. Panics if the argument is of another type.
+    pub fn int(&self, index: usize) -> i64 {
+        match self.args[index] {
+            Value::Int(n) => n,
+            ref value => panic!("argument {} of {} is {:?}, not an int", index, self.name, value),
+        }
+    }
+
+    /// An `ArgType::Float` argument. Panics if the argument is of another type.
+    pub fn float(&self, index: usize) -> f32 {
+        match self.args[index] {
+            Value::Float(n) => n,
+            ref value => panic!("argument {} of {} is {:?}, not a float", index, self.name, value),
+        }
+    }
+
+    /// An `ArgType::Bool` argument. Panics if the argument is of another type.
+    pub fn bool(&self, index: usize) -> bool {
+        match self.args[index] {
+            Value::Bool(b) => b,
+            ref value => panic!("argument {} of {} is {:?}, not a bool", index, self.name, value),
+        }
+    }
+
+    /// An `ArgType::Str` argument. Panics if the argument is of another type.
+    pub fn str(&self, index: usize) -> &str {
+        match &self.args[index] {
+            Value::Str(s) => s,
+            value => panic!("argument {} of {} is {:?}, not text", index, self.name, value),
+        }
+    }
+}
+
+struct Spec {
+    args: Vec<ArgType>,
+    help: String,
+    /// Handled by the engine rather than passed on to the game.
+    builtin: bool,
+}
+
+pub struct Console {
+    /// The key which opens and closes the console, or `None` for no key. Defaults to
+    /// the backtick.
+    pub toggle_key: Option<Input>,
+    open: bool,
+    /// The line being typed, and where in it the cursor is, in characters.
+    line: Vec<char>,
+    cursor: usize,
+    /// Lines entered, oldest first.
+    history: Vec<String>,
+    /// The line of the history being shown, while going back through it.
+    history_pos: Option<usize>,
+    specs: BTreeMap<String, Spec>,
+    /// Commands entered for the game to take.
+    queue: VecDeque<Command>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        let mut console = Self {
+            toggle_key: Some(Input::Character('`')),
+            open: false,
+            line: Vec::new(),
+            cursor: 0,
+            history: Vec::new(),
+            history_pos: None,
+            specs: BTreeMap::new(),
+            queue: VecDeque::new(),
+        };
+
+        let builtins: &[(&str, &str)] = &[
+            ("clear", "clear the log"),
+            ("help", "list commands, or describe one: help [command]"),
+            ("quit", "stop the game"),
+            ("set", "show or change a setting: set [name] [value]"),
+        ];
+        for &(name, help) in builtins {
+            console.specs.insert(
+                name.to_owned(),
+                Spec {
+                    args: Vec::new(),
+                    help: help.to_owned(),
+                    builtin: true,
+                },
+            );
+        }
+        console
+    }
+
+    /// Add a command for the game to handle, replacing any registered with the same
+    /// name. It's only passed on once it's been given arguments of these types.
+    pub fn register(&mut self, name: &str, args: &[ArgType], help: &str) {
+        self.specs.insert(
+            name.to_owned(),
+            Spec {
+                args: args.to_vec(),
+                help: help.to_owned(),
+                builtin: false,
+            },
+        );
+    }
+
+    /// The oldest command typed which the game hasn't taken yet.
+    pub fn next_command(&mut self) -> Option<Command> {
+        self.queue.pop_front()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    /// Lines entered, oldest first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Edit the line being typed with this frame's input, returning the lines entered.
+    pub(crate) fn handle_input(&mut self, input: &InputState, log: &mut Log) -> Vec<String> {
+        let mut entered = Vec::new();
+        for &event in input.events() {
+            if Some(event) == self.toggle_key {
+                self.open = !self.open;
+                continue;
+            }
+            if !self.open {
+                continue;
+            }
+
+            match event {
+                Input::Character('\n') | Input::Character('\r') | Input::KeyEnter => {
+                    let line = self.line.drain(..).collect::<String>();
+                    self.cursor = 0;
+                    self.history_pos = None;
+                    if !line.trim().is_empty() {
+                        if self.history.last() != Some(&line) {
+                            self.history.push(line.clone());
+                            if self.history.len() > MAX_HISTORY {
+                                self.history.remove(0);
+                            }
+                        }
+                        entered.push(line);
+                    }
+                }
+                Input::Character('\u{1b}') => self.open = false,
+                Input::Character('\t') => self.complete(log),
+                Input::KeyBackspace | Input::Character('\u{7f}') | Input::Character('\u{8}')
+                    if self.cursor > 0 =>
+                {
+                    self.cursor -= 1;
+                    self.line.remove(self.cursor);
+                }
+                Input::KeyDC if self.cursor < self.line.len() => {
+                    self.line.remove(self.cursor);
+                }
+                // Ctrl-U clears the line
+                Input::Character('\u{15}') => {
+                    self.line.clear();
+                    self.cursor = 0;
+                }
+                Input::KeyLeft => self.cursor = self.cursor.saturating_sub(1),
+                Input::KeyRight => self.cursor = (self.cursor + 1).min(self.line.len()),
+                Input::KeyHome => self.cursor = 0,
+                Input::KeyEnd => self.cursor = self.line.len(),
+                Input::KeyUp => self.step_history(-1),
+                Input::KeyDown => self.step_history(1),
+                Input::Character(c) if !c.is_control() => {
+                    self.line.insert(self.cursor, c);
+                    self.cursor += 1;
+                }
+                _ => {}
+            }
+        }
+
+        if self.open {
+            log.scroll_with(input);
+        }
+        entered
+    }
+
+    /// Replace the line with the previous or next line in the history.
+    fn step_history(&mut self, step: i32) {
+        let pos = match (self.history_pos, step < 0) {
+            (None, true) if !self.history.is_empty() => Some(self.history.len() - 1),
+            (None, _) => return,
+            (Some(pos), true) => Some(pos.saturating_sub(1)),
+            (Some(pos), false) if pos + 1 < self.history.len() => Some(pos + 1),
+            (Some(_), false) => None, // Back to an empty line
+        };
+
+        self.history_pos = pos;
+        self.line = pos.map_or(Vec::new(), |pos| self.history[pos].chars().collect());
+        self.cursor = self.line.len();
+    }
+
+    /// Complete the command name, or the setting name after `set`, being typed. If
+    /// there's more than one way to complete it, the choices are logged.
+    fn complete(&mut self, log: &mut Log) {
+        let before = self.line[..self.cursor].iter().collect::<String>();
+        let words = before.split(' ').collect::<Vec<_>>();
+        let word = *words.last().unwrap();
+        let choices = match words.len() {
+            1 => self.specs.keys().map(String::as_str).collect::<Vec<_>>(),
+            2 if words[0] == "set" => VARIABLES.to_vec(),
+            2 if words[0] == "help" => self.specs.keys().map(String::as_str).collect(),
+            _ => return,
+        };
+        let matches = choices.into_iter().filter(|c| c.starts_with(word)).collect::<Vec<_>>();
+
+        // Complete as far as every match agrees
+        let mut common = match matches.first() {
+            Some(first) => first.to_string(),
+            None => return,
+        };
+        for m in &matches[1..] {
+            let len = common.chars().zip(m.chars()).take_while(|(a, b)| a == b).count();
+            common = common.chars().take(len).collect();
+        }
+        let mut insert = common.chars().skip(word.chars().count()).collect::<Vec<_>>();
+        if matches.len() == 1 {
+            insert.push(' ');
+        } else if insert.is_empty() {
+            log.info(&matches.join("  "));
+        }
+
+        for c in insert {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    /// Check a line against the commands registered. Returns the command, and whether
+    /// it's built in, or a message saying what's wrong with it.
+    fn parse(&self, line: &str) -> Result<(Command, bool), String> {
+        let words = split_words(line)?;
+        let (name, words) = match words.split_first() {
+            Some((name, words)) => (name, words),
+            None => return Err("nothing to run".to_owned()),
+        };
+        let spec = match self.specs.get(name) {
+            Some(spec) => spec,
+            None => return Err(format!("unknown command {:?}; try help", name)),
+        };
+
+        // Built-in commands check their own arguments
+        if spec.builtin {
+            let args = words.iter().map(|w| Value::Str(w.clone())).collect();
+            return Ok((Command { name: name.clone(), args }, true));
+        }
+
+        let bad_args = || format!("usage: {}", usage(name, spec));
+        if words.len() != spec.args.len() {
+            return Err(bad_args());
+        }
+        let mut args = Vec::new();
+        for (word, ty) in words.iter().zip(&spec.args) {
+            args.push(ty.parse(word).ok_or_else(bad_args)?);
+        }
+        Ok((Command { name: name.clone(), args }, false))
+    }
+
+    /// Draw the console over the top of the screen, with the log above the line being
+    /// typed.
+    pub(crate) fn draw(&self, backend: &mut EasyCurses, log: &Log) {
+        let (h, w) = backend.get_row_col_count();
+        if h <= 0 || w <= 0 {
+            return;
+        }
+        let rows = panel_rows(h);
+        log.draw_history(backend, rows - 1);
+
+        let prompt = "> ";
+        if w as usize <= prompt.len() {
+            return; // No room for the line
+        }
+
+        // Scroll the line sideways to keep the cursor in view
+        let room = (w as usize).saturating_sub(prompt.len() + 1).max(1);
+        let first = (self.cursor + 1).saturating_sub(room);
+        let shown = self.line.iter().skip(first).take(room).collect::<String>();
+
+        let y = rows as i32 - 1;
+        backend.set_color_pair(ColorPair::new(Color::White, Color::Blue));
+        backend.move_rc(y, 0);
+        backend.print(format!("{}{:2$}", prompt, shown, (w as usize).saturating_sub(prompt.len())));
+
+        let under_cursor = self.line.get(self.cursor).copied().unwrap_or(' ');
+        backend.set_color_pair(ColorPair::new(Color::Blue, Color::White));
+        backend.move_rc(y, (prompt.len() + self.cursor - first) as i32);
+        backend.print_char(under_cursor);
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Term3D {
+    /// Run a line as though it had been typed into the console: built-in commands run
+    /// now, and the game's are queued for `Console::next_command`.
+    pub fn run_command(&mut self, line: &str) {
+        self.log.push_colored(crate::logging::Level::Info, Color::Green, &format!("> {}", line));
+        let (command, builtin) = match self.console.parse(line) {
+            Ok(parsed) => parsed,
+            Err(message) => return self.log.error(&message),
+        };
+        if !builtin {
+            self.console.queue.push_back(command);
+            return;
+        }
+
+        let args = command.args.iter().map(|arg| match arg {
+            Value::Str(s) => s.as_str(),
+            _ => "",
+        });
+        let args = args.collect::<Vec<_>>();
+        match (command.name.as_str(), args.as_slice()) {
+            ("clear", []) => self.log.clear(),
+            ("quit", []) => self.quit(),
+            ("help", []) => {
+                let lines = self
+                    .console
+                    .specs
+                    .iter()
+                    .map(|(name, spec)| format!("{} - {}", usage(name, spec), spec.help))
+                    .collect::<Vec<_>>();
+                for line in lines {
+                    self.log.info(&line);
+                }
+            }
+            ("help", [name]) => match self.console.specs.get(*name) {
+                Some(spec) => {
+                    let line = format!("{} - {}", usage(name, spec), spec.help);
+                    self.log.info(&line);
+                }
+                None => self.log.error(&format!("unknown command {:?}", name)),
+            },
+            ("set", []) => {
+                for name in VARIABLES {
+                    let line = format!("{} = {}", name, self.setting(name));
+                    self.log.info(&line);
+                }
+            }
+            ("set", [name]) if VARIABLES.contains(name) => {
+                let line = format!("{} = {}", name, self.setting(name));
+                self.log.info(&line);
+            }
+            ("set", [name, value]) if VARIABLES.contains(name) => {
+                if self.change_setting(name, value) {
+                    let line = format!("{} = {}", name, self.setting(name));
+                    self.log.info(&line);
+                } else {
+                    self.log.error(&format!("bad value {:?} for {}", value, name));
+                }
+            }
+            ("set", _) => self.log.error(&format!("settings are {}", VARIABLES.join(", "))),
+            (name, _) => {
+                let spec = &self.console.specs[name];
+                let line = format!("usage: {}", usage(name, spec));
+                self.log.error(&line);
+            }
+        }
+    }
+
+    /// Take this frame's console input, running any lines entered. While the console
+    /// is open, the keyboard input is kept from the game.
+    pub(crate) fn update_console(&mut self) {
+        let was_open = self.console.is_open();
+        let entered = self.console.handle_input(&self.input, &mut self.log);
+        if was_open || self.console.is_open() {
+            self.input.consume_keys();
+        }
+        for line in entered {
+            self.run_command(&line);
+        }
+    }
+
+    /// The value of a setting for the built-in `set` command, as text.
+    fn setting(&self, name: &str) -> String {
+        match name {
+            "fps" => self.target_fps.map_or("unlimited".to_owned(), |fps| fps.to_string()),
+            "lifetime" => self.log.lifetime.to_string(),
+            "picking" => self.picking.to_string(),
+            "threads" => self.render_threads.to_string(),
+            "timestamps" => self.log.timestamps.to_string(),
+            "timestep" => self.fixed_timestep.map_or("off".to_owned(), |step| step.to_string()),
+            _ => String::new(),
+        }
+    }
+
+    /// Change a setting for the built-in `set` command, returning whether the value
+    /// made sense.
+    fn change_setting(&mut self, name: &str, value: &str) -> bool {
+        match name {
+            "fps" => match value.parse::<u32>() {
+                Ok(fps) => self.target_fps = Some(fps).filter(|&fps| fps > 0),
+                Err(_) => return false,
+            },
+            "lifetime" => match value.parse::<f32>() {
+                Ok(secs) if secs >= 0. => self.log.lifetime = secs,
+                _ => return false,
+            },
+            "picking" => match parse_bool(value) {
+                Some(on) => self.picking = on,
+                None => return false,
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) if threads > 0 => self.render_threads = threads,
+                _ => return false,
+            },
+            "timestamps" => match parse_bool(value) {
+                Some(on) => self.log.timestamps = on,
+                None => return false,
+            },
+            "timestep" => match value.parse::<f32>() {
+                Ok(step) if step >= 0. => self.fixed_timestep = Some(step).filter(|&step| step > 0.),
+                _ => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text {
+        "true" | "on" | "1" => Some(true),
+        "false" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// A command's name followed by the types of its arguments, like `tp <float> <float>`.
+fn usage(name: &str, spec: &Spec) -> String {
+    let mut usage = name.to_owned();
+    for arg in &spec.args {
+        usage += &format!(" <{}>", arg.name());
+    }
+    usage
+}
+
+/// Split a line into words at spaces, keeping text in double quotes together.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut word = String::new();
+        match chars.peek() {
+            None => return Ok(words),
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".to_owned()),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+            }
+        }
+        words.push(word);
+    }
+}
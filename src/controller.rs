@@ -0,0 +1,255 @@
+//! Ready-made ways of moving a `Camera` around.
+//!
+//! Each controller is kept by the game and updated from `Game::update`, with the
+//! camera it should move:
+//!
+//! ```ignore
+//! self.controller.update(&mut term.cameras[0], &term.input, delta);
+//! ```
+
+use crate::core::transform_point;
+use crate::glm::Vec4;
+use crate::input::InputState;
+use crate::scene::{ObjectHandle, Objects};
+use crate::Camera;
+
+use easycurses::Input;
+
+use std::f32::consts::FRAC_PI_2;
+
+/// The keys a controller responds to. Each action may be bound to any number of keys.
+#[derive(Clone)]
+pub struct Bindings {
+    pub forward: Vec<Input>,
+    pub back: Vec<Input>,
+    pub left: Vec<Input>,
+    pub right: Vec<Input>,
+    pub up: Vec<Input>,
+    pub down: Vec<Input>,
+    pub look_up: Vec<Input>,
+    pub look_down: Vec<Input>,
+    pub look_left: Vec<Input>,
+    pub look_right: Vec<Input>,
+    pub zoom_in: Vec<Input>,
+    pub zoom_out: Vec<Input>,
+}
+
+impl Bindings {
+    /// WASD to move, Q and E to go down and up, the arrow keys to look around, and
+    /// + and - to zoom.
+    pub fn new() -> Self {
+        Self {
+            forward: vec![Input::Character('w')],
+            back: vec![Input::Character('s')],
+            left: vec![Input::Character('a')],
+            right: vec![Input::Character('d')],
+            up: vec![Input::Character('e')],
+            down: vec![Input::Character('q')],
+            look_up: vec![Input::KeyUp],
+            look_down: vec![Input::KeyDown],
+            look_left: vec![Input::KeyLeft],
+            look_right: vec![Input::KeyRight],
+            zoom_in: vec![Input::Character('+'), Input::Character('=')],
+            zoom_out: vec![Input::Character('-')],
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn held(keys: &[Input], input: &InputState) -> bool {
+    keys.iter().any(|key| input.is_down(key))
+}
+
+/// How far the held keys push along an axis: -1, 0 or 1.
+fn axis(negative: &[Input], positive: &[Input], input: &InputState) -> f32 {
+    held(positive, input) as i32 as f32 - held(negative, input) as i32 as f32
+}
+
+/// Turn the camera with the look keys.
+fn look(cam: &mut Camera, bindings: &Bindings, turn: f32, input: &InputState) {
+    cam.transform.rot.0 += axis(&bindings.look_up, &bindings.look_down, input) * turn;
+    cam.transform.rot.1 += axis(&bindings.look_left, &bindings.look_right, input) * turn;
+}
+
+/// Moves freely in the direction the camera is looking, like a spectator.
+pub struct FlyController {
+    /// Units moved per second.
+    pub speed: f32,
+    /// Radians turned per second.
+    pub look_speed: f32,
+    pub bindings: Bindings,
+}
+
+impl FlyController {
+    pub fn new() -> Self {
+        Self {
+            speed: 10.,
+            look_speed: 10.,
+            bindings: Bindings::new(),
+        }
+    }
+
+    pub fn update(&mut self, cam: &mut Camera, input: &InputState, delta: f32) {
+        let s = self.speed * delta;
+        let forward = axis(&self.bindings.back, &self.bindings.forward, input) * s;
+        let right = axis(&self.bindings.left, &self.bindings.right, input) * s;
+        let up = axis(&self.bindings.down, &self.bindings.up, input) * s;
+
+        let (fx, fy, fz) = cam.forward();
+        let (rx, _, rz) = cam.right();
+        let pos = &mut cam.transform.pos;
+        pos.0 += fx * forward + rx * right;
+        pos.1 += fy * forward - up; // Up is towards -y
+        pos.2 += fz * forward + rz * right;
+
+        look(cam, &self.bindings, self.look_speed * delta, input);
+    }
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Walks along the ground in the direction the camera faces, with the camera's pitch
+/// kept between looking straight up and straight down.
+pub struct FpsController {
+    /// Units moved per second.
+    pub speed: f32,
+    /// Radians turned per second.
+    pub look_speed: f32,
+    /// The furthest the camera can pitch up or down, in radians.
+    pub pitch_limit: f32,
+    pub bindings: Bindings,
+}
+
+impl FpsController {
+    pub fn new() -> Self {
+        Self {
+            speed: 10.,
+            look_speed: 10.,
+            pitch_limit: FRAC_PI_2 * 0.95,
+            bindings: Bindings::new(),
+        }
+    }
+
+    pub fn update(&mut self, cam: &mut Camera, input: &InputState, delta: f32) {
+        let s = self.speed * delta;
+        let forward = axis(&self.bindings.back, &self.bindings.forward, input) * s;
+        let right = axis(&self.bindings.left, &self.bindings.right, input) * s;
+        let up = axis(&self.bindings.down, &self.bindings.up, input) * s;
+
+        let yaw = cam.transform.rot.1;
+        let pos = &mut cam.transform.pos;
+        pos.0 += yaw.sin() * forward + yaw.cos() * right;
+        pos.1 -= up; // Up is towards -y
+        pos.2 += yaw.cos() * forward - yaw.sin() * right;
+
+        look(cam, &self.bindings, self.look_speed * delta, input);
+        let pitch = &mut cam.transform.rot.0;
+        *pitch = pitch.clamp(-self.pitch_limit, self.pitch_limit);
+    }
+}
+
+impl Default for FpsController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Circles around a point, always facing it. The look keys move the camera around the
+/// target and the zoom keys move it closer or further away.
+pub struct OrbitController {
+    /// The point in world space being orbited.
+    pub target: [f32; 3],
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Radians turned per second.
+    pub look_speed: f32,
+    /// Units zoomed per second.
+    pub zoom_speed: f32,
+    /// The furthest the camera can go over or under the target, in radians.
+    pub pitch_limit: f32,
+    pub bindings: Bindings,
+}
+
+impl OrbitController {
+    pub fn new(target: [f32; 3], distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            min_distance: 1.,
+            max_distance: 100.,
+            look_speed: 2.,
+            zoom_speed: 10.,
+            pitch_limit: FRAC_PI_2 * 0.95,
+            bindings: Bindings::new(),
+        }
+    }
+
+    pub fn update(&mut self, cam: &mut Camera, input: &InputState, delta: f32) {
+        let zoom = axis(&self.bindings.zoom_in, &self.bindings.zoom_out, input);
+        self.distance = (self.distance + zoom * self.zoom_speed * delta)
+            .clamp(self.min_distance, self.max_distance);
+
+        look(cam, &self.bindings, self.look_speed * delta, input);
+        let pitch = &mut cam.transform.rot.0;
+        *pitch = pitch.clamp(-self.pitch_limit, self.pitch_limit);
+
+        // Back away from the target along the direction the camera faces
+        let (fx, fy, fz) = cam.forward();
+        cam.set_world_position(self.target);
+        let pos = &mut cam.transform.pos;
+        pos.0 -= fx * self.distance;
+        pos.1 -= fy * self.distance;
+        pos.2 -= fz * self.distance;
+    }
+}
+
+/// A third-person camera which smoothly follows an object from behind, facing it.
+pub struct FollowController {
+    pub target: ObjectHandle,
+    /// Where the camera should be, in the target's space. Negative z is behind it and
+    /// negative y is above it.
+    pub offset: [f32; 3],
+    /// How quickly the camera catches up with where it should be. Higher is snappier,
+    /// and 0 never moves.
+    pub smoothing: f32,
+}
+
+impl FollowController {
+    pub fn new(target: ObjectHandle) -> Self {
+        Self {
+            target,
+            offset: [0., -3., -8.],
+            smoothing: 5.,
+        }
+    }
+
+    /// Does nothing if the target has been despawned.
+    pub fn update(&mut self, cam: &mut Camera, objects: &Objects, delta: f32) {
+        let world = match objects.world_matrix(self.target) {
+            Some(world) => world,
+            None => return,
+        };
+        let target = transform_point(&world, [0., 0., 0.]);
+        let offset = world * Vec4::new(self.offset[0], self.offset[1], self.offset[2], 0.);
+
+        // Exponential smoothing, so the camera moves the same way at any frame rate
+        let t = 1. - (-self.smoothing * delta).exp();
+        let mut pos = cam.world_position();
+        pos[0] += (target[0] + offset.x - pos[0]) * t;
+        pos[1] += (target[1] + offset.y - pos[1]) * t;
+        pos[2] += (target[2] + offset.z - pos[2]) * t;
+        cam.set_world_position(pos);
+
+        cam.look_at(target);
+    }
+}
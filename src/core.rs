@@ -1,6 +1,6 @@
 use easycurses::{EasyCurses, ColorPair};
 
-use crate::glm::IVec2;
+use crate::glm::{IVec2, Mat4, Vec2, Vec4};
 
 use std::cmp::{max, min};
 
@@ -14,6 +14,12 @@ pub fn rotate_2d(pos: (f32, f32), rad: f32) -> (f32, f32) {
     (x * c - y * s, y * c + x * s)
 }
 
+/// Transform a point by a matrix, as if it had a w component of 1.
+pub fn transform_point(m: &Mat4, p: [f32; 3]) -> [f32; 3] {
+    let v = m * Vec4::new(p[0], p[1], p[2], 1.);
+    [v.x, v.y, v.z]
+}
+
 pub fn draw_cell(e: &mut EasyCurses, c: char, x: i32, y: i32) {
     // Top left is origin
     e.move_rc(y, x);
@@ -115,44 +121,180 @@ pub fn tri_bounding_box(v1: IVec2, v2: IVec2, v3: IVec2) -> (i32, i32, i32, i32)
     (min_x, max_x, min_y, max_y)
 }
 
-pub fn draw_tri(e: &mut EasyCurses, color: ColorPair, v1: IVec2, v2: IVec2, v3: IVec2) {
-    // calculate triangle bounding box
-    let (minx, maxx, miny, maxy) = {
-        let (minx, maxx, miny, maxy) = tri_bounding_box(v1, v2, v3);
-        // Clip box against render target bounds
-        let (mut emax_y, mut emax_x) = e.get_row_col_count();
-        emax_y -= 1;
-        emax_x -= 1;
-        (
-            min(emax_x, max(0, minx)),
-            min(emax_x, max(0, maxx)),
-            min(emax_y, max(0, miny)),
-            min(emax_y, max(0, maxy)),
-        )
-    };
-
-    let vs1 = IVec2::new(v2.x - v1.x, v2.y - v1.y);
-    let vs2 = IVec2::new(v3.x - v1.x, v3.y - v1.y);
+/// Number of fractional bits kept on vertex positions by the rasteriser.
+const SUBCELL_BITS: u32 = 4;
+/// One cell in rasteriser fixed-point units.
+const SUBCELL: i64 = 1 << SUBCELL_BITS;
+/// Vertices further than this many cells from the origin are clamped, keeping
+/// the edge function products well inside an `i64`.
+const COORD_LIMIT: f32 = (1 << 20) as f32;
 
-    e.set_color_pair(color);
-    for x in minx..=maxx {
-        for y in miny..=maxy {
-            let q = IVec2::new(x - v1.x, y - v1.y);
-
-            let perp_dot_product_vs1_vs2 = perp_ivec2(&vs1).dot(&vs2) as f32;
-            let s = perp_ivec2(&q).dot(&vs2) as f32 / perp_dot_product_vs1_vs2;
-            let t = perp_ivec2(&vs1).dot(&q) as f32 / perp_dot_product_vs1_vs2;
-            //let s = q.perp_dot_product(&vs2) / vs1.perp_dot_product(&vs2);
-            //let t = vs1.perp_dot_product(&q) / vs1.perp_dot_product(&vs2);
-
-            if (s >= 0.) && (t >= 0.) && (s + t <= 1.) {
-                draw_cell(e, '#', x, y);
+fn to_fixed(v: Vec2) -> (i64, i64) {
+    let snap = |f: f32| (f.clamp(-COORD_LIMIT, COORD_LIMIT) * SUBCELL as f32).round() as i64;
+    (snap(v.x), snap(v.y))
+}
+
+/// An edge function `E(p) = (b - a) x (p - a)` which is stepped across the grid
+/// by addition only.
+struct Edge {
+    /// Change in the edge function for a step of one cell along x.
+    step_x: i64,
+    /// Change in the edge function for a step of one cell along y.
+    step_y: i64,
+    /// Value at the centre of the first cell of the current row.
+    row: i64,
+}
+
+impl Edge {
+    fn new(a: (i64, i64), b: (i64, i64), origin: (i64, i64)) -> Self {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        // Top-left fill rule: a cell centre lying exactly on an edge only belongs to
+        // the triangle if that edge is a top edge or a left edge. With y pointing
+        // down and the winding used here, those are the edges going right along a
+        // row, or going up.
+        let top_left = (dy == 0 && dx > 0) || dy < 0;
+        let bias = if top_left { 0 } else { -1 };
+        Self {
+            step_x: -dy * SUBCELL,
+            step_y: dx * SUBCELL,
+            row: dx * (origin.1 - a.1) - dy * (origin.0 - a.0) + bias,
+        }
+    }
+}
+
+/// Calls `plot` with the position of every cell whose centre lies inside the triangle,
+/// limited to the inclusive `(minimum x, maximum x, minimum y, maximum y)` clip box.
+///
+/// Vertices are kept at sub-cell precision and a top-left fill rule is applied, so
+/// triangles sharing an edge cover every cell along it exactly once.
+pub fn rasterize_tri<F: FnMut(i32, i32)>(
+    clip: (i32, i32, i32, i32),
+    v1: Vec2,
+    v2: Vec2,
+    v3: Vec2,
+    mut plot: F,
+) {
+    let (a, mut b, mut c) = (to_fixed(v1), to_fixed(v2), to_fixed(v3));
+
+    let area = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+    if area == 0 {
+        return; // Degenerate triangles cover nothing
+    } else if area < 0 {
+        std::mem::swap(&mut b, &mut c);
+    }
+
+    // Cells which could have their centre inside the triangle, clipped to the target
+    let cell = |f: i64| (f >> SUBCELL_BITS) as i32;
+    let minx = max(clip.0, cell(a.0.min(b.0).min(c.0)));
+    let maxx = min(clip.1, cell(a.0.max(b.0).max(c.0)));
+    let miny = max(clip.2, cell(a.1.min(b.1).min(c.1)));
+    let maxy = min(clip.3, cell(a.1.max(b.1).max(c.1)));
+    if minx > maxx || miny > maxy {
+        return;
+    }
+
+    let origin = (
+        i64::from(minx) * SUBCELL + SUBCELL / 2,
+        i64::from(miny) * SUBCELL + SUBCELL / 2,
+    );
+    let mut edges = [Edge::new(b, c, origin), Edge::new(c, a, origin), Edge::new(a, b, origin)];
+
+    for y in miny..=maxy {
+        let (mut w0, mut w1, mut w2) = (edges[0].row, edges[1].row, edges[2].row);
+        for x in minx..=maxx {
+            if (w0 | w1 | w2) >= 0 {
+                plot(x, y);
             }
+            w0 += edges[0].step_x;
+            w1 += edges[1].step_x;
+            w2 += edges[2].step_x;
+        }
+        for edge in &mut edges {
+            edge.row += edge.step_y;
         }
     }
 }
 
-pub fn draw_quad(e: &mut EasyCurses, color: ColorPair, a: IVec2, b: IVec2, c: IVec2, d: IVec2) {
+pub fn draw_tri(e: &mut EasyCurses, color: ColorPair, v1: Vec2, v2: Vec2, v3: Vec2) {
+    // Clip against render target bounds
+    let (h, w) = e.get_row_col_count();
+
+    e.set_color_pair(color);
+    rasterize_tri((0, w - 1, 0, h - 1), v1, v2, v3, |x, y| {
+        draw_cell(e, '#', x, y);
+    });
+}
+
+pub fn draw_quad(e: &mut EasyCurses, color: ColorPair, a: Vec2, b: Vec2, c: Vec2, d: Vec2) {
+    // Both halves share the a-c diagonal, which the fill rule splits between them
     draw_tri(e, color, a, b, c);
-    draw_tri(e, color, a, d, c);
+    draw_tri(e, color, a, c, d);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glm::vec2;
+
+    const SIZE: usize = 8;
+
+    /// How many times each cell of a `SIZE` by `SIZE` grid is covered by the triangles.
+    fn coverage(tris: &[[Vec2; 3]]) -> [[u32; SIZE]; SIZE] {
+        let mut grid = [[0; SIZE]; SIZE];
+        let clip = (0, SIZE as i32 - 1, 0, SIZE as i32 - 1);
+        for t in tris {
+            rasterize_tri(clip, t[0], t[1], t[2], |x, y| grid[y as usize][x as usize] += 1);
+        }
+        grid
+    }
+
+    fn assert_covered_once(grid: &[[u32; SIZE]; SIZE]) {
+        for (y, row) in grid.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                assert_eq!(count, 1, "cell ({}, {}) covered {} times", x, y, count);
+            }
+        }
+    }
+
+    #[test]
+    fn quad_halves_tile_the_diagonal() {
+        // Every cell centre on the diagonal lies exactly on the shared edge
+        let (a, b, c, d) = (vec2(0., 0.), vec2(8., 0.), vec2(8., 8.), vec2(0., 8.));
+        assert_covered_once(&coverage(&[[a, b, c], [a, c, d]]));
+        assert_covered_once(&coverage(&[[a, b, d], [b, c, d]]));
+    }
+
+    #[test]
+    fn fan_tiles_shared_edges() {
+        // Edges meeting at off-grid points, and edges through cell centres
+        for &centre in &[vec2(4., 4.), vec2(3.3, 4.7), vec2(2.5, 6.5)] {
+            let corners = [vec2(0., 0.), vec2(8., 0.), vec2(8., 8.), vec2(0., 8.)];
+            let tris = (0..4).map(|i| [centre, corners[i], corners[(i + 1) % 4]]).collect::<Vec<_>>();
+            assert_covered_once(&coverage(&tris));
+        }
+    }
+
+    #[test]
+    fn winding_does_not_matter() {
+        let (a, b, c) = (vec2(0.7, 0.2), vec2(7.3, 2.9), vec2(2.1, 7.6));
+        assert_eq!(coverage(&[[a, b, c]]), coverage(&[[a, c, b]]));
+        assert!(coverage(&[[a, b, c]]).iter().flatten().any(|&n| n > 0));
+    }
+
+    #[test]
+    fn degenerate_triangles_cover_nothing() {
+        let line = [vec2(0.5, 0.5), vec2(4.5, 4.5), vec2(7.5, 7.5)];
+        let point = [vec2(2.5, 2.5); 3];
+        assert!(coverage(&[line, point]).iter().flatten().all(|&n| n == 0));
+    }
+
+    #[test]
+    fn clip_box_is_respected() {
+        let mut cells = Vec::new();
+        rasterize_tri((2, 4, 1, 3), vec2(-10., -10.), vec2(20., -10.), vec2(-10., 20.), |x, y| {
+            cells.push((x, y))
+        });
+        assert_eq!(cells.len(), 3 * 3);
+        assert!(cells.iter().all(|&(x, y)| (2..=4).contains(&x) && (1..=3).contains(&y)));
+    }
 }
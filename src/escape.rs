@@ -0,0 +1,516 @@
+//! Decoding the escape sequences terminals send for input curses doesn't understand,
+//! like SGR mouse reports and kitty's keyboard protocol.
+//!
+//! Curses hands these over one character at a time, so they're gathered back into
+//! sequences here. Anything which turns out not to be a sequence we know is passed on
+//! unchanged, as the keys it was made of. A sequence may be split between reads, so
+//! an unfinished one is only given up on once no more of it has come for a while.
+
+use crate::input::{KeyAction, KeyEvent, Modifiers, MouseAction, MouseButton, MouseEvent};
+
+use easycurses::Input;
+
+use std::time::{Duration, Instant};
+
+/// Longest run of parameters kept before a sequence is given up on.
+const MAX_PARAMS: usize = 32;
+
+/// How long to wait for the rest of a sequence before passing on what was received of
+/// it, such as a lone Escape key press.
+pub const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A key as curses reported it, with nothing more known about it.
+    Key(Input),
+    /// A key press, repeat or release reported with kitty's keyboard protocol, or a
+    /// legacy key sequence with modifiers.
+    Keyboard(KeyEvent),
+    Mouse(MouseEvent),
+    /// The terminal's answer to a query of which keyboard protocol flags are on.
+    KeyboardFlags(u32),
+    /// The terminal's answer to a query of its device attributes.
+    DeviceAttributes,
+    /// The terminal gained or lost focus, with focus reporting on.
+    Focus(bool),
+}
+
+enum State {
+    Ground,
+    /// After an Escape.
+    Escape,
+    /// Inside a control sequence, after `ESC [`.
+    Csi,
+}
+
+pub struct Decoder {
+    state: State,
+    /// Everything received for the sequence so far, to pass on if it isn't one.
+    raw: Vec<Input>,
+    /// Parameter and intermediate characters of the control sequence so far.
+    params: String,
+    /// When the last input arrived.
+    last_input: Option<Instant>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            raw: Vec::new(),
+            params: String::new(),
+            last_input: None,
+        }
+    }
+
+    /// Decode one input from curses, adding any events it completes to `out`.
+    pub fn push(&mut self, input: Input, out: &mut Vec<Event>) {
+        self.last_input = Some(Instant::now());
+        match self.state {
+            State::Ground => match input {
+                Input::Character('\u{1b}') => {
+                    self.raw.push(input);
+                    self.state = State::Escape;
+                }
+                // Curses recognises the start of an SGR mouse report as the key some
+                // terminals use for mouse events, and leaves the rest to us
+                Input::KeyMouse => {
+                    self.raw.push(input);
+                    self.params.push('<');
+                    self.state = State::Csi;
+                }
+                _ => out.push(Event::Key(input)),
+            },
+            State::Escape => match input {
+                Input::Character('[') => {
+                    self.raw.push(input);
+                    self.state = State::Csi;
+                }
+                _ => {
+                    self.flush(out);
+                    self.push(input, out);
+                }
+            },
+            State::Csi => match input {
+                Input::Character(c @ '\u{20}'..='\u{3f}') if self.params.len() < MAX_PARAMS => {
+                    self.raw.push(input);
+                    self.params.push(c);
+                }
+                Input::Character(c @ '\u{40}'..='\u{7e}') => {
+                    self.raw.push(input);
+                    match decode_csi(&self.params, c) {
+                        Some(event) => {
+                            out.push(event);
+                            self.reset();
+                        }
+                        None => self.flush(out),
+                    }
+                }
+                _ => {
+                    self.flush(out);
+                    self.push(input, out);
+                }
+            },
+        }
+    }
+
+    /// Give up on any unfinished sequence, passing on what was received of it as keys.
+    pub fn flush(&mut self, out: &mut Vec<Event>) {
+        out.extend(self.raw.drain(..).map(Event::Key));
+        self.reset();
+    }
+
+    /// Give up on an unfinished sequence if none of it has arrived for
+    /// `SEQUENCE_TIMEOUT` as of `now`. This is how a lone Escape key press comes
+    /// through, so it should be called whenever there's no more input waiting.
+    pub fn flush_stale(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let waited = self.last_input.map_or(SEQUENCE_TIMEOUT, |last| now.saturating_duration_since(last));
+        if waited >= SEQUENCE_TIMEOUT {
+            self.flush(out);
+        }
+    }
+
+    /// Decode bytes as read from the terminal, as though curses had passed on each
+    /// character, then flush. Useful for checking the decoder against recorded input.
+    pub fn decode_bytes(&mut self, bytes: &[u8]) -> Vec<Event> {
+        let mut out = Vec::new();
+        for c in String::from_utf8_lossy(bytes).chars() {
+            self.push(Input::Character(c), &mut out);
+        }
+        self.flush(&mut out);
+        out
+    }
+
+    fn reset(&mut self) {
+        self.raw.clear();
+        self.params.clear();
+        self.state = State::Ground;
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The event a complete control sequence stands for, if it's one we know.
+fn decode_csi(params: &str, last: char) -> Option<Event> {
+    match (params.chars().next(), last) {
+        (Some('<'), 'M') | (Some('<'), 'm') => decode_sgr_mouse(&params[1..], last == 'm').map(Event::Mouse),
+        (Some('?'), 'u') => params[1..].parse().ok().map(Event::KeyboardFlags),
+        (Some('?'), 'c') => Some(Event::DeviceAttributes),
+        (None, 'I') => Some(Event::Focus(true)),
+        (None, 'O') => Some(Event::Focus(false)),
+        (Some(c), _) if !c.is_ascii_digit() && c != ';' => None,
+        _ => decode_key(params, last).map(Event::Keyboard),
+    }
+}
+
+/// A key in kitty's keyboard protocol, `ESC [ code:shifted ; modifiers:action u`, or
+/// one of the legacy sequences it extends, like `ESC [ 1 ; modifiers:action A` for
+/// the up arrow and `ESC [ 5 ; modifiers:action ~` for page up.
+fn decode_key(params: &str, last: char) -> Option<KeyEvent> {
+    let mut fields = params.split(';');
+    let mut codes = fields.next().unwrap_or("").split(':');
+    let mut state = fields.next().unwrap_or("").split(':');
+    let number = |n: Option<&str>, default: u32| match n {
+        None | Some("") => Some(default),
+        Some(n) => n.parse::<u32>().ok(),
+    };
+
+    let code = number(codes.next(), 1)?;
+    let shifted = number(codes.next(), 0)?;
+    let mods = number(state.next(), 1)?.saturating_sub(1);
+    let action = match number(state.next(), 1)? {
+        1 => KeyAction::Press,
+        2 => KeyAction::Repeat,
+        3 => KeyAction::Release,
+        _ => return None,
+    };
+
+    let key = match last {
+        'u' => kitty_key(code)?,
+        '~' => match code {
+            2 => Input::KeyIC,
+            3 => Input::KeyDC,
+            5 => Input::KeyPPage,
+            6 => Input::KeyNPage,
+            7 => Input::KeyHome,
+            8 => Input::KeyEnd,
+            11 => Input::KeyF1,
+            12 => Input::KeyF2,
+            13 => Input::KeyF3,
+            14 => Input::KeyF4,
+            15 => Input::KeyF5,
+            17 => Input::KeyF6,
+            18 => Input::KeyF7,
+            19 => Input::KeyF8,
+            20 => Input::KeyF9,
+            21 => Input::KeyF10,
+            23 => Input::KeyF11,
+            24 => Input::KeyF12,
+            _ => return None,
+        },
+        _ if code != 1 => return None,
+        'A' => Input::KeyUp,
+        'B' => Input::KeyDown,
+        'C' => Input::KeyRight,
+        'D' => Input::KeyLeft,
+        'E' => Input::KeyB2,
+        'F' => Input::KeyEnd,
+        'H' => Input::KeyHome,
+        'P' => Input::KeyF1,
+        'Q' => Input::KeyF2,
+        'R' => Input::KeyF3,
+        'S' => Input::KeyF4,
+        _ => return None,
+    };
+
+    Some(KeyEvent {
+        key,
+        shifted: std::char::from_u32(shifted).filter(|_| shifted != 0),
+        action,
+        modifiers: Modifiers {
+            shift: mods & 1 != 0,
+            alt: mods & 2 != 0,
+            ctrl: mods & 4 != 0,
+        },
+    })
+}
+
+/// The key for a code in kitty's keyboard protocol, matching what curses reports for
+/// the same key where it can.
+fn kitty_key(code: u32) -> Option<Input> {
+    Some(match code {
+        9 => Input::Character('\t'),
+        13 => Input::Character('\n'),
+        127 => Input::KeyBackspace,
+        // Keys on the keypad
+        57399..=57408 => Input::Character(std::char::from_digit(code - 57399, 10)?),
+        57409 => Input::Character('.'),
+        57410 => Input::Character('/'),
+        57411 => Input::Character('*'),
+        57412 => Input::Character('-'),
+        57413 => Input::Character('+'),
+        57414 => Input::KeyEnter,
+        57415 => Input::Character('='),
+        57417 => Input::KeyLeft,
+        57418 => Input::KeyRight,
+        57419 => Input::KeyUp,
+        57420 => Input::KeyDown,
+        57421 => Input::KeyPPage,
+        57422 => Input::KeyNPage,
+        57423 => Input::KeyHome,
+        57424 => Input::KeyEnd,
+        57425 => Input::KeyIC,
+        57426 => Input::KeyDC,
+        57376 => Input::KeyF13,
+        57377 => Input::KeyF14,
+        57378 => Input::KeyF15,
+        // Other keys in the private use area, like the modifier keys themselves
+        57344..=63743 => Input::Unknown(code as i32),
+        _ => Input::Character(std::char::from_u32(code)?),
+    })
+}
+
+/// An SGR (1006) mouse report, `ESC [ < button ; column ; row M`, ending in `m`
+/// instead for button releases.
+fn decode_sgr_mouse(params: &str, release: bool) -> Option<MouseEvent> {
+    let mut numbers = params.split(';').map(|n| n.parse::<i32>().ok());
+    let (code, column, row) = (numbers.next()??, numbers.next()??, numbers.next()??);
+    if numbers.next().is_some() {
+        return None;
+    }
+
+    // The low bits give the button, and the high bits what kind of event it was
+    let button = match (code & 128 != 0, code & 3) {
+        (false, 0) => Some(MouseButton::Left),
+        (false, 1) => Some(MouseButton::Middle),
+        (false, 2) => Some(MouseButton::Right),
+        (true, n) => Some(MouseButton::Other(8 + n as u8)),
+        _ => None,
+    };
+    let action = if code & 64 != 0 {
+        match code & 3 {
+            0 => MouseAction::ScrollUp,
+            1 => MouseAction::ScrollDown,
+            2 => MouseAction::ScrollLeft,
+            _ => MouseAction::ScrollRight,
+        }
+    } else if code & 32 != 0 {
+        button.map_or(MouseAction::Move, MouseAction::Drag)
+    } else if release {
+        MouseAction::Release(button?)
+    } else {
+        MouseAction::Press(button?)
+    };
+
+    Some(MouseEvent {
+        action,
+        // Reports count from 1
+        x: column - 1,
+        y: row - 1,
+        modifiers: Modifiers {
+            shift: code & 4 != 0,
+            alt: code & 8 != 0,
+            ctrl: code & 16 != 0,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Push the characters of `text`, then flush as a frame ending at `now` would.
+    fn poll(decoder: &mut Decoder, text: &str, now: Instant) -> Vec<Event> {
+        let mut out = Vec::new();
+        for c in text.chars() {
+            decoder.push(Input::Character(c), &mut out);
+        }
+        decoder.flush_stale(now, &mut out);
+        out
+    }
+
+    #[test]
+    fn sequences_split_between_reads_are_kept() {
+        let mut decoder = Decoder::new();
+        assert_eq!(poll(&mut decoder, "\x1b[<35;1", Instant::now()), vec![]);
+        assert_eq!(poll(&mut decoder, "0;5", Instant::now()), vec![]);
+        let events = poll(&mut decoder, "M", Instant::now());
+        assert_eq!(
+            events,
+            vec![Event::Mouse(MouseEvent {
+                action: MouseAction::Move,
+                x: 9,
+                y: 4,
+                modifiers: Modifiers::default(),
+            })]
+        );
+    }
+
+    #[test]
+    fn lone_escape_comes_through_after_the_timeout() {
+        let mut decoder = Decoder::new();
+        assert_eq!(poll(&mut decoder, "\x1b", Instant::now()), vec![]);
+        let later = Instant::now() + SEQUENCE_TIMEOUT;
+        assert_eq!(poll(&mut decoder, "", later), vec![Event::Key(Input::Character('\x1b'))]);
+        assert_eq!(poll(&mut decoder, "", later), vec![]);
+    }
+
+    fn keys(text: &str) -> Vec<Event> {
+        text.chars().map(|c| Event::Key(Input::Character(c))).collect()
+    }
+
+    fn key(key: Input, action: KeyAction, modifiers: Modifiers, shifted: Option<char>) -> Event {
+        Event::Keyboard(KeyEvent {
+            key,
+            shifted,
+            action,
+            modifiers,
+        })
+    }
+
+    fn mouse(action: MouseAction, x: i32, y: i32, modifiers: Modifiers) -> Event {
+        Event::Mouse(MouseEvent { action, x, y, modifiers })
+    }
+
+    const NONE: Modifiers = Modifiers {
+        shift: false,
+        alt: false,
+        ctrl: false,
+    };
+    const SHIFT: Modifiers = Modifiers { shift: true, ..NONE };
+    const ALT: Modifiers = Modifiers { alt: true, ..NONE };
+    const CTRL: Modifiers = Modifiers { ctrl: true, ..NONE };
+
+    #[test]
+    fn kitty_key_actions() {
+        let a = Input::Character('a');
+        let cases: &[(&[u8], Event)] = &[
+            (b"\x1b[97u", key(a, KeyAction::Press, NONE, None)),
+            (b"\x1b[97;1u", key(a, KeyAction::Press, NONE, None)),
+            (b"\x1b[97;1:1u", key(a, KeyAction::Press, NONE, None)),
+            (b"\x1b[97;1:2u", key(a, KeyAction::Repeat, NONE, None)),
+            (b"\x1b[97;1:3u", key(a, KeyAction::Release, NONE, None)),
+            (b"\x1b[13u", key(Input::Character('\n'), KeyAction::Press, NONE, None)),
+            (b"\x1b[127;1:3u", key(Input::KeyBackspace, KeyAction::Release, NONE, None)),
+            (b"\x1b[57414u", key(Input::KeyEnter, KeyAction::Press, NONE, None)),
+            (b"\x1b[57376;1:2u", key(Input::KeyF13, KeyAction::Repeat, NONE, None)),
+        ];
+        for &(bytes, event) in cases {
+            assert_eq!(Decoder::new().decode_bytes(bytes), vec![event], "decoding {:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn kitty_modifiers_and_shifted_keys() {
+        let both = Modifiers { alt: true, ctrl: true, ..NONE };
+        let cases: &[(&[u8], Event)] = &[
+            (b"\x1b[97:65;2u", key(Input::Character('a'), KeyAction::Press, SHIFT, Some('A'))),
+            (b"\x1b[49:33;2:3u", key(Input::Character('1'), KeyAction::Release, SHIFT, Some('!'))),
+            (b"\x1b[97;2u", key(Input::Character('a'), KeyAction::Press, SHIFT, None)),
+            (b"\x1b[99;5u", key(Input::Character('c'), KeyAction::Press, CTRL, None)),
+            (b"\x1b[99;7:2u", key(Input::Character('c'), KeyAction::Repeat, both, None)),
+            (b"\x1b[57441;2u", key(Input::Unknown(57441), KeyAction::Press, SHIFT, None)),
+            (b"\x1b[57441;1:3u", key(Input::Unknown(57441), KeyAction::Release, NONE, None)),
+        ];
+        for &(bytes, event) in cases {
+            assert_eq!(Decoder::new().decode_bytes(bytes), vec![event], "decoding {:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn legacy_key_sequences() {
+        let cases: &[(&[u8], Event)] = &[
+            (b"\x1b[1;5A", key(Input::KeyUp, KeyAction::Press, CTRL, None)),
+            (b"\x1b[1;2:3B", key(Input::KeyDown, KeyAction::Release, SHIFT, None)),
+            (b"\x1b[1;3C", key(Input::KeyRight, KeyAction::Press, ALT, None)),
+            (b"\x1b[D", key(Input::KeyLeft, KeyAction::Press, NONE, None)),
+            (b"\x1b[1;1:2H", key(Input::KeyHome, KeyAction::Repeat, NONE, None)),
+            (b"\x1b[1;2P", key(Input::KeyF1, KeyAction::Press, SHIFT, None)),
+            (b"\x1b[5~", key(Input::KeyPPage, KeyAction::Press, NONE, None)),
+            (b"\x1b[3;3~", key(Input::KeyDC, KeyAction::Press, ALT, None)),
+            (b"\x1b[24;1:2~", key(Input::KeyF12, KeyAction::Repeat, NONE, None)),
+            (b"\x1b[15;5:3~", key(Input::KeyF5, KeyAction::Release, CTRL, None)),
+        ];
+        for &(bytes, event) in cases {
+            assert_eq!(Decoder::new().decode_bytes(bytes), vec![event], "decoding {:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn terminal_replies() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.decode_bytes(b"\x1b[?15u"), vec![Event::KeyboardFlags(15)]);
+        assert_eq!(decoder.decode_bytes(b"\x1b[?0u"), vec![Event::KeyboardFlags(0)]);
+        assert_eq!(decoder.decode_bytes(b"\x1b[?62;22c"), vec![Event::DeviceAttributes]);
+        assert_eq!(decoder.decode_bytes(b"\x1b[I\x1b[O"), vec![Event::Focus(true), Event::Focus(false)]);
+    }
+
+    #[test]
+    fn unknown_sequences_pass_through() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"hi", "hi"),
+            (b"\x1b[99x", "\x1b[99x"),
+            (b"\x1b[99~", "\x1b[99~"),
+            (b"\x1b[5;9Z", "\x1b[5;9Z"),
+            (b"\x1b[>1u", "\x1b[>1u"),
+            (b"\x1b[97;1:9u", "\x1b[97;1:9u"),
+            (b"\x1b[?xu", "\x1b[?xu"),
+            (b"\x1b[<0;1M", "\x1b[<0;1M"),
+            (b"\x1bOA", "\x1bOA"),
+            (b"\x1b[1", "\x1b[1"),
+        ];
+        for &(bytes, text) in cases {
+            assert_eq!(Decoder::new().decode_bytes(bytes), keys(text), "decoding {:?}", bytes);
+        }
+
+        // A sequence after a false start is still decoded
+        let mut expected = keys("\x1b");
+        expected.push(key(Input::KeyUp, KeyAction::Press, NONE, None));
+        assert_eq!(Decoder::new().decode_bytes(b"\x1b\x1b[A"), expected);
+    }
+
+    #[test]
+    fn sgr_mouse_reports() {
+        let cases: &[(&[u8], Event)] = &[
+            (b"\x1b[<0;1;1M", mouse(MouseAction::Press(MouseButton::Left), 0, 0, NONE)),
+            (b"\x1b[<0;1;1m", mouse(MouseAction::Release(MouseButton::Left), 0, 0, NONE)),
+            (b"\x1b[<1;80;24M", mouse(MouseAction::Press(MouseButton::Middle), 79, 23, NONE)),
+            (b"\x1b[<18;10;5M", mouse(MouseAction::Press(MouseButton::Right), 9, 4, CTRL)),
+            (b"\x1b[<32;3;4M", mouse(MouseAction::Drag(MouseButton::Left), 2, 3, NONE)),
+            (b"\x1b[<35;3;4M", mouse(MouseAction::Move, 2, 3, NONE)),
+            (b"\x1b[<39;3;4M", mouse(MouseAction::Move, 2, 3, SHIFT)),
+            (b"\x1b[<64;5;6M", mouse(MouseAction::ScrollUp, 4, 5, NONE)),
+            (b"\x1b[<65;5;6M", mouse(MouseAction::ScrollDown, 4, 5, NONE)),
+            (b"\x1b[<74;5;6M", mouse(MouseAction::ScrollLeft, 4, 5, ALT)),
+            (b"\x1b[<128;2;2M", mouse(MouseAction::Press(MouseButton::Other(8)), 1, 1, NONE)),
+            (b"\x1b[<129;2;2m", mouse(MouseAction::Release(MouseButton::Other(9)), 1, 1, NONE)),
+        ];
+        for &(bytes, event) in cases {
+            assert_eq!(Decoder::new().decode_bytes(bytes), vec![event], "decoding {:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn mouse_reports_started_by_curses() {
+        // Curses may report the `ESC [ <` of a mouse report as KeyMouse
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        decoder.push(Input::KeyMouse, &mut out);
+        out.extend(decoder.decode_bytes(b"2;7;3M"));
+        assert_eq!(out, vec![mouse(MouseAction::Press(MouseButton::Right), 6, 2, NONE)]);
+    }
+
+    #[test]
+    fn mixed_stream() {
+        let mut expected = keys("a");
+        expected.push(key(Input::Character('a'), KeyAction::Release, NONE, None));
+        expected.push(mouse(MouseAction::ScrollUp, 0, 0, NONE));
+        expected.extend(keys("b"));
+        let events = Decoder::new().decode_bytes(b"a\x1b[97;1:3u\x1b[<64;1;1Mb");
+        assert_eq!(events, expected);
+    }
+}
@@ -0,0 +1,390 @@
+//! Tracking which keys are held down, from the key presses a terminal reports.
+//!
+//! Terminals only report key presses, repeating them while a key is held, and never
+//! report key releases. A key is taken to be held from when it is first pressed until
+//! it hasn't repeated for a little longer than the terminal's key repeat delay.
+//!
+//! Terminals supporting kitty's keyboard protocol can report key releases too, once
+//! it's turned on with `Term3D::set_kitty_keyboard`, and keys are then held until
+//! they're really released. The mouse has no such problem, since terminals always
+//! report its buttons being released.
+
+use easycurses::Input;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyAction {
+    Press,
+    Repeat,
+    Release,
+}
+
+/// A key event with more detail than curses gives, from kitty's keyboard protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The key, as typed without shift, so its press and release match whichever
+    /// modifiers are held.
+    pub key: Input,
+    /// The character typed with shift held, if it was held and the terminal said.
+    pub shifted: Option<char>,
+    pub action: KeyAction,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// The key as curses would have reported it, taking modifiers into account.
+    pub fn typed(&self) -> Input {
+        match self.key {
+            Input::Character(c) if self.modifiers.ctrl && c.is_ascii_alphabetic() => {
+                Input::Character((c.to_ascii_lowercase() as u8 & 0x1f) as char)
+            }
+            Input::Character(c) if self.modifiers.shift => {
+                Input::Character(self.shifted.unwrap_or_else(|| c.to_ascii_uppercase()))
+            }
+            key => key,
+        }
+    }
+}
+
+/// Kitty's codes for the modifier keys themselves, which curses never reports.
+const MODIFIER_KEYS: std::ops::RangeInclusive<i32> = 57441..=57452;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    /// Any other button, by its number in xterm's reports, such as 8 and 9 for back
+    /// and forward.
+    Other(u8),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    /// The mouse moved with a button held.
+    Drag(MouseButton),
+    /// The mouse moved with no buttons held.
+    Move,
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+/// Modifier keys held during an event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// The cell the mouse was over, from the left of the terminal.
+    pub x: i32,
+    /// The cell the mouse was over, from the top of the terminal.
+    pub y: i32,
+    pub modifiers: Modifiers,
+}
+
+struct Hold {
+    /// When the key was last reported.
+    last_seen: Instant,
+    /// Whether the key has been reported more than once since it was pressed.
+    repeating: bool,
+}
+
+pub struct InputState {
+    /// How long after a key is first pressed it's taken to be released if it hasn't
+    /// been reported again. This should be longer than the terminal's delay before a
+    /// held key starts repeating.
+    pub initial_repeat_timeout: Duration,
+    /// How long after a key last repeated it's taken to be released if it hasn't been
+    /// reported again. This should be longer than the time between key repeats.
+    pub repeat_timeout: Duration,
+    held: HashMap<Input, Hold>,
+    pressed: Vec<Input>,
+    released: Vec<Input>,
+    events: Vec<Input>,
+    key_events: Vec<KeyEvent>,
+    /// Whether the terminal reports key releases, so keys shouldn't time out.
+    key_releases: bool,
+    modifiers: Modifiers,
+    mouse_events: Vec<MouseEvent>,
+    mouse_pos: Option<(i32, i32)>,
+    mouse_held: Vec<MouseButton>,
+    mouse_pressed: Vec<MouseButton>,
+    mouse_released: Vec<MouseButton>,
+    mouse_motion: (i32, i32),
+    scroll: i32,
+    resized: bool,
+    focused: bool,
+    focus_changed: bool,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self {
+            initial_repeat_timeout: Duration::from_millis(600),
+            repeat_timeout: Duration::from_millis(100),
+            held: HashMap::new(),
+            pressed: Vec::new(),
+            released: Vec::new(),
+            events: Vec::new(),
+            key_events: Vec::new(),
+            key_releases: false,
+            modifiers: Modifiers::default(),
+            mouse_events: Vec::new(),
+            mouse_pos: None,
+            mouse_held: Vec::new(),
+            mouse_pressed: Vec::new(),
+            mouse_released: Vec::new(),
+            mouse_motion: (0, 0),
+            scroll: 0,
+            resized: false,
+            focused: true,
+            focus_changed: false,
+        }
+    }
+
+    /// Whether a key is being held down.
+    pub fn is_down(&self, key: &Input) -> bool {
+        self.held.contains_key(key)
+    }
+
+    /// Whether a key started being held down this frame.
+    pub fn just_pressed(&self, key: &Input) -> bool {
+        self.pressed.contains(key)
+    }
+
+    /// Whether a key stopped being held down this frame.
+    pub fn just_released(&self, key: &Input) -> bool {
+        self.released.contains(key)
+    }
+
+    /// Every key being held down, in no particular order.
+    pub fn down(&self) -> impl Iterator<Item = &Input> {
+        self.held.keys()
+    }
+
+    /// Everything the terminal reported this frame, in order, including key repeats
+    /// and events like `Input::KeyResize`.
+    pub fn events(&self) -> &[Input] {
+        &self.events
+    }
+
+    /// Key presses, repeats and releases reported with kitty's keyboard protocol this
+    /// frame, in order. Presses and repeats are in `events` as well.
+    pub fn key_events(&self) -> &[KeyEvent] {
+        &self.key_events
+    }
+
+    /// Whether the terminal reports key releases, rather than keys being taken to be
+    /// released once they stop repeating.
+    pub fn reports_releases(&self) -> bool {
+        self.key_releases
+    }
+
+    /// The modifier keys held during the last key or mouse event which said.
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Everything the mouse did this frame, in order.
+    pub fn mouse_events(&self) -> &[MouseEvent] {
+        &self.mouse_events
+    }
+
+    /// The cell the mouse was last seen over, or `None` if it hasn't been seen yet.
+    pub fn mouse_pos(&self) -> Option<(i32, i32)> {
+        self.mouse_pos
+    }
+
+    /// Whether a mouse button is being held down.
+    pub fn mouse_down(&self, button: MouseButton) -> bool {
+        self.mouse_held.contains(&button)
+    }
+
+    /// Whether a mouse button was pressed this frame.
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_pressed.contains(&button)
+    }
+
+    /// Whether a mouse button was released this frame.
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.mouse_released.contains(&button)
+    }
+
+    /// How many cells the mouse moved across and down this frame, for mouse look.
+    pub fn mouse_motion(&self) -> (i32, i32) {
+        self.mouse_motion
+    }
+
+    /// How many steps the wheel was scrolled this frame, positive for up and negative
+    /// for down.
+    pub fn scroll(&self) -> i32 {
+        self.scroll
+    }
+
+    /// Whether the terminal was resized this frame.
+    pub fn resized(&self) -> bool {
+        self.resized
+    }
+
+    /// Whether the terminal has focus. Terminals which don't report focus are always
+    /// taken to have it.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Whether the terminal gained or lost focus this frame.
+    pub fn focus_changed(&self) -> bool {
+        self.focus_changed
+    }
+
+    /// Forget everything from the last frame, ready for this frame's events.
+    pub(crate) fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.events.clear();
+        self.key_events.clear();
+        self.mouse_events.clear();
+        self.mouse_pressed.clear();
+        self.mouse_released.clear();
+        self.mouse_motion = (0, 0);
+        self.scroll = 0;
+        self.resized = false;
+        self.focus_changed = false;
+    }
+
+    /// Forget the keys reported this frame and any still held, so nothing else sees
+    /// them, as when they were typed into the console.
+    pub(crate) fn consume_keys(&mut self) {
+        self.pressed.clear();
+        self.events.clear();
+        self.key_events.clear();
+        self.held.clear();
+    }
+
+    /// Record the terminal gaining or losing focus.
+    pub(crate) fn set_focus(&mut self, focused: bool) {
+        if focused != self.focused {
+            self.focused = focused;
+            self.focus_changed = true;
+        }
+    }
+
+    /// Record a mouse event reported by the terminal.
+    pub(crate) fn push_mouse(&mut self, event: MouseEvent) {
+        if let Some((x, y)) = self.mouse_pos {
+            self.mouse_motion.0 += event.x - x;
+            self.mouse_motion.1 += event.y - y;
+        }
+        self.mouse_pos = Some((event.x, event.y));
+        self.modifiers = event.modifiers;
+
+        match event.action {
+            MouseAction::Press(button) if !self.mouse_held.contains(&button) => {
+                self.mouse_held.push(button);
+                self.mouse_pressed.push(button);
+            }
+            MouseAction::Release(button) => {
+                if let Some(i) = self.mouse_held.iter().position(|&b| b == button) {
+                    self.mouse_held.remove(i);
+                    self.mouse_released.push(button);
+                }
+            }
+            MouseAction::ScrollUp => self.scroll += 1,
+            MouseAction::ScrollDown => self.scroll -= 1,
+            _ => {}
+        }
+
+        self.mouse_events.push(event);
+    }
+
+    /// Record an event reported by the terminal.
+    pub(crate) fn push(&mut self, event: Input, now: Instant) {
+        self.events.push(event);
+
+        match event {
+            Input::KeyResize => self.resized = true,
+            Input::KeyMouse => {} // Not a key
+            _ => self.hold(event, now),
+        }
+    }
+
+    /// Record a key event reported with kitty's keyboard protocol.
+    pub(crate) fn push_key(&mut self, event: KeyEvent, now: Instant) {
+        self.modifiers = event.modifiers;
+        match event.action {
+            KeyAction::Press | KeyAction::Repeat => {
+                match event.key {
+                    Input::Unknown(code) if MODIFIER_KEYS.contains(&code) => {}
+                    _ => self.events.push(event.typed()),
+                }
+                self.hold(event.key, now);
+            }
+            KeyAction::Release => {
+                if self.held.remove(&event.key).is_some() {
+                    self.released.push(event.key);
+                }
+            }
+        }
+        self.key_events.push(event);
+    }
+
+    /// Set whether the terminal reports key releases.
+    pub(crate) fn set_reports_releases(&mut self, releases: bool) {
+        self.key_releases = releases;
+    }
+
+    /// Mark a key as held, or as repeating if it already was.
+    fn hold(&mut self, key: Input, now: Instant) {
+        match self.held.get_mut(&key) {
+            Some(hold) => {
+                hold.last_seen = now;
+                hold.repeating = true;
+            }
+            None => {
+                self.held.insert(
+                    key,
+                    Hold {
+                        last_seen: now,
+                        repeating: false,
+                    },
+                );
+                self.pressed.push(key);
+            }
+        }
+    }
+
+    /// Release the keys which have gone unreported for too long, unless the terminal
+    /// reports releases itself.
+    pub(crate) fn release_stale(&mut self, now: Instant) {
+        if self.key_releases {
+            return;
+        }
+        let (initial, repeat) = (self.initial_repeat_timeout, self.repeat_timeout);
+        let released = &mut self.released;
+        self.held.retain(|&key, hold| {
+            let timeout = if hold.repeating { repeat } else { initial };
+            let down = now.duration_since(hold.last_seen) <= timeout;
+            if !down {
+                released.push(key);
+            }
+            down
+        });
+    }
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,42 +1,156 @@
-#![feature(vec_remove_item)]
-
 pub use easycurses::*;
 pub use nalgebra_glm as glm;
 
-use ordered_float::NotNan;
-
+pub mod actions;
+pub mod bounds;
+pub mod console;
+pub mod controller;
 pub mod core;
-
+pub mod escape;
+pub mod input;
+pub mod logging;
+pub mod path;
+pub mod raster;
+pub mod raycast;
+mod render;
+pub mod scene;
+mod signals;
+pub mod state;
+
+use crate::actions::ActionMap;
+use crate::bounds::Bounds;
+use crate::console::Console;
 use crate::core::*;
-
-use glm::IVec2;
-
-use std::cmp::{max, min};
-use std::thread::sleep;
+use crate::escape::{Decoder, Event};
+use crate::input::{InputState, KeyAction};
+use crate::logging::{Level, Log};
+use crate::path::PathPlayback;
+use crate::raster::Framebuffer;
+use crate::scene::{ObjectHandle, Objects};
+
+use glm::Mat4;
+
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::thread::{self, sleep};
 use std::time::{Duration, Instant};
 
+/// Distance in cells between the centre of the screen and where a point one unit to the
+/// side of the camera and one unit in front of it is drawn.
+pub const PROJECTION_SCALE: f32 = 200.;
+
+/// A rectangle of the terminal, in cells.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
 pub struct Camera {
     pub transform: Transform,
+    /// Bitmask of the render layers drawn by this camera. All layers are drawn by default.
+    pub layers: u32,
+    /// The part of the terminal drawn to, which is blanked before drawing. `None` draws
+    /// over the whole terminal without blanking it.
+    pub viewport: Option<Viewport>,
+    /// Cameras are drawn in increasing order, so higher ones are drawn over lower ones.
+    /// Cameras with the same order are drawn in the order they are in `Term3D::cameras`.
+    pub order: i32,
+    /// The path the camera is following, if any. See `Camera::play`.
+    pub path: Option<PathPlayback>,
 }
 
 impl Camera {
     pub fn new(pos: (f32, f32, f32), rot: (f32, f32)) -> Self {
         Self {
             transform: Transform { pos, rot },
+            layers: u32::MAX,
+            viewport: None,
+            order: 0,
+            path: None,
         }
     }
+
+    /// Transform a point from world space into the camera's view space, where the camera
+    /// is at the origin looking down +z and +y points down the screen.
+    pub fn view(&self, point: [f32; 3]) -> [f32; 3] {
+        let point = world_to_view(point);
+        let pos = self.transform.pos;
+        let (x, y, z) = (point[0] - pos.0, point[1] - pos.1, point[2] - pos.2);
+        let (x, z) = rotate_2d((x, z), self.transform.rot.1);
+        let (y, z) = rotate_2d((y, z), self.transform.rot.0);
+        [x, y, z]
+    }
+
+    /// Where the camera is in world space. `transform.pos` is kept in view space, which
+    /// is squashed vertically.
+    pub fn world_position(&self) -> [f32; 3] {
+        let (x, y, z) = self.transform.pos;
+        view_to_world([x, y, z])
+    }
+
+    /// Move the camera to a point in world space.
+    pub fn set_world_position(&mut self, pos: [f32; 3]) {
+        let [x, y, z] = world_to_view(pos);
+        self.transform.pos = (x, y, z);
+    }
+
+    /// The direction the camera is looking in, as a unit vector in the same space as
+    /// `transform.pos`.
+    pub fn forward(&self) -> (f32, f32, f32) {
+        let (pitch, yaw) = self.transform.rot;
+        (pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos())
+    }
+
+    /// The direction to the right of the camera, ignoring pitch.
+    pub fn right(&self) -> (f32, f32, f32) {
+        let yaw = self.transform.rot.1;
+        (yaw.cos(), 0., -yaw.sin())
+    }
+
+    /// Turn the camera to face a point in world space.
+    pub fn look_at(&mut self, target: [f32; 3]) {
+        let (target, pos) = (world_to_view(target), self.transform.pos);
+        let (dx, dy, dz) = (target[0] - pos.0, target[1] - pos.1, target[2] - pos.2);
+        let yaw = dx.atan2(dz);
+        let pitch = dy.atan2((dx * dx + dz * dz).sqrt());
+        self.transform.rot = (pitch, yaw);
+    }
+}
+
+/// Scale a point from world space to the space cameras are positioned in. Terminal cells
+/// are about twice as tall as they are wide, so heights are halved.
+fn world_to_view(point: [f32; 3]) -> [f32; 3] {
+    [point[0], point[1] / 2., point[2]]
+}
+
+fn view_to_world(point: [f32; 3]) -> [f32; 3] {
+    [point[0], point[1] * 2., point[2]]
 }
 
+#[derive(Clone)]
 pub struct Mesh {
     pub verts: Vec<[f32; 3]>,
     pub faces: Vec<([f32; 4], Option<Color>)>,
+    bounds: Bounds,
 }
 
 impl Mesh {
+    pub fn new(verts: Vec<[f32; 3]>, faces: Vec<([f32; 4], Option<Color>)>) -> Self {
+        Self {
+            bounds: Bounds::from_points(&verts),
+            verts,
+            faces,
+        }
+    }
+
     #[inline]
     pub fn cube() -> Self {
-        Self {
-            verts: vec![
+        Self::new(
+            vec![
                 [-1., -1., -1.],
                 [1., -1., -1.],
                 [1., 1., -1.],
@@ -46,7 +160,7 @@ impl Mesh {
                 [1., 1., 1.],
                 [-1., 1., 1.],
             ],
-            faces: vec![
+            vec![
                 ([0., 1., 2., 3.], None),
                 ([4., 5., 6., 7.], None),
                 ([0., 1., 5., 4.], None),
@@ -54,7 +168,18 @@ impl Mesh {
                 ([0., 3., 7., 4.], None),
                 ([1., 2., 6., 5.], None),
             ],
-        }
+        )
+    }
+
+    /// Bounding volumes of the vertices, as of the last call to `update_bounds`.
+    pub fn bounds(&self) -> &Bounds {
+        &self.bounds
+    }
+
+    /// Recalculate the bounding volumes. This must be called after changing `verts`,
+    /// or the mesh may be culled while it is still on screen.
+    pub fn update_bounds(&mut self) {
+        self.bounds = Bounds::from_points(&self.verts);
     }
 }
 
@@ -70,235 +195,414 @@ impl Transform {
             rot: (0., 0.),
         }
     }
+
+    /// The matrix taking points from an object's space to world space: rotating by
+    /// `rot.0` around the x axis, then by `rot.1` around the y axis, then moving by `pos`.
+    pub fn matrix(&self) -> Mat4 {
+        glm::translation(&glm::vec3(self.pos.0, self.pos.1, self.pos.2))
+            * glm::rotation(self.rot.1, &glm::vec3(0., 1., 0.))
+            * glm::rotation(self.rot.0, &glm::vec3(1., 0., 0.))
+    }
 }
 
 pub struct Object {
     pub transform: Transform,
-    pub mesh: Mesh,
+    /// The mesh may be shared between many objects. Use `Rc::make_mut` to edit it,
+    /// which copies the mesh first if any other object is using it.
+    pub mesh: Rc<Mesh>,
+    /// Can be used to look the object up with `Objects::find`.
+    pub name: Option<String>,
+    /// Can be used to look up groups of objects with `Objects::tagged`.
+    pub tags: Vec<String>,
+    /// Hidden objects are not drawn. This doesn't affect the object's children.
+    pub visible: bool,
+    /// Bitmask of the render layers the object is on. It is only drawn by cameras
+    /// with at least one of these layers in their `layers` mask. Objects start out on
+    /// just the first layer, `1`.
+    pub layers: u32,
+    parent: Option<ObjectHandle>,
+    /// World matrix as of the last frame rendered.
+    world: Mat4,
 }
 
 impl Object {
     pub fn new(mesh: Mesh) -> Self {
+        Self::instance(&Rc::new(mesh))
+    }
+
+    /// Create an object sharing the mesh with every other instance of it, rather
+    /// than owning a copy.
+    pub fn instance(mesh: &Rc<Mesh>) -> Self {
         Self {
             transform: Transform::new(),
-            mesh,
+            mesh: Rc::clone(mesh),
+            name: None,
+            tags: Vec::new(),
+            visible: true,
+            layers: 1,
+            parent: None,
+            world: glm::identity(),
         }
     }
 }
 
 pub trait Game {
     fn start(&mut self, term: &mut Term3D);
-    fn update(&mut self, term: &mut Term3D, delta: f32, key: Option<Input>);
+    /// Called once a frame. What the player is pressing is in `term.input`.
+    fn update(&mut self, term: &mut Term3D, delta: f32);
+    /// Called every `Term3D::fixed_timestep` seconds with that timestep, before `update`,
+    /// as many times as needed to catch up. Deterministic logic such as physics goes here.
+    /// `Term3D::alpha` tells how far between two fixed updates a frame is drawn.
+    fn fixed_update(&mut self, _term: &mut Term3D, _step: f32) {}
+    /// Called after `update`, just before the scene is drawn, for last changes to what's
+    /// drawn.
+    fn pre_render(&mut self, _term: &mut Term3D) {}
+    /// Called once the scene has been drawn, so anything drawn here goes on top of it.
+    /// HUDs and menus go here.
+    fn draw_overlay(&mut self, _term: &mut Term3D) {}
+    /// Called when the terminal is resized, with its new width and height in cells.
+    fn on_resize(&mut self, _term: &mut Term3D, _width: i32, _height: i32) {}
+    /// Called when the terminal gains or loses focus, in terminals which report it.
+    fn on_focus_change(&mut self, _term: &mut Term3D, _focused: bool) {}
+    /// Called once `run` stops, while the terminal can still be drawn to. Saving and
+    /// cleaning up goes here.
+    fn on_exit(&mut self, _term: &mut Term3D) {}
 }
 
+/// The most time a single frame can add for fixed updates to catch up on, so a long
+/// stall doesn't leave the game running fixed updates forever.
+const MAX_FIXED_CATCH_UP: f32 = 0.25;
+
 pub struct Term3D {
     pub backend: EasyCurses,
-    pub cam: Camera,
-    pub objects: Vec<Object>,
-    pub log: Vec<(String, Color, Duration)>, // Lines of text being drawn
+    /// Starts out with a single camera drawing to the whole terminal.
+    pub cameras: Vec<Camera>,
+    pub objects: Objects,
+    /// Keys and mouse buttons held and pressed, as of the last call to `poll_input`.
+    pub input: InputState,
+    /// Named actions and axes bound to keys, checked with `Term3D::action_down` and friends.
+    pub actions: ActionMap,
+    /// Lines of text drawn over the game, and the scrollback of every line.
+    pub log: Log,
+    /// The drop-down console, for commands registered by the game and a few built in.
+    pub console: Console,
+    /// Number of threads faces are rasterised on. Defaults to the number of CPUs.
+    pub render_threads: usize,
+    /// Record which object and face is drawn in each cell, for `Term3D::pick`.
+    pub picking: bool,
+    /// Frames per second `run` aims for, sleeping off any time left over at the end of
+    /// each frame. `None` runs as fast as possible. Defaults to 60.
+    pub target_fps: Option<u32>,
+    /// Seconds between calls to `Game::fixed_update`, or `None` to never call it.
+    pub fixed_timestep: Option<f32>,
+    /// The key which stops `run` when pressed. Defaults to Escape; `None` leaves
+    /// stopping to the game, through `Term3D::quit`.
+    pub quit_key: Option<Input>,
+    /// Whether `Term3D::quit` has been called.
+    quitting: bool,
+    /// Time passed which fixed updates haven't been run for yet.
+    accumulator: f32,
+    /// Gathers escape sequences, like mouse reports, from what curses reads.
+    decoder: Decoder,
+    /// Whether mouse reporting is on.
+    mouse_reporting: bool,
+    framebuffer: Framebuffer,
+    /// What each triangle id in the framebuffer was drawn with.
+    fills: Vec<render::Fill>,
 }
 
 impl Term3D {
     pub fn new() -> Self {
-        Self {
-            backend: EasyCurses::initialize_system().unwrap(),
-            cam: Camera::new((0., 0., 0.), (0., 0.)),
-            objects: Vec::new(),
-            log: Vec::new(),
-        }
+        signals::save_terminal();
+        let mut backend = EasyCurses::initialize_system().unwrap();
+        backend.set_input_mode(InputMode::Character);
+        backend.set_input_timeout(TimeoutMode::Immediate);
+        backend.set_cursor_visibility(CursorVisibility::Invisible);
+        backend.set_keypad_enabled(true);
+        backend.set_echo(false);
+
+        let mut term = Self {
+            backend,
+            cameras: vec![Camera::new((0., 0., 0.), (0., 0.))],
+            objects: Objects::new(),
+            input: InputState::new(),
+            actions: ActionMap::new(),
+            log: Log::new(),
+            console: Console::new(),
+            render_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            picking: false,
+            target_fps: Some(60),
+            fixed_timestep: None,
+            quit_key: Some(Input::Character('\u{1b}')),
+            quitting: false,
+            accumulator: 0.,
+            decoder: Decoder::new(),
+            mouse_reporting: false,
+            framebuffer: Framebuffer::new(0, 0),
+            fills: Vec::new(),
+        };
+        term.set_mouse_reporting(true);
+        term.set_focus_reporting(true);
+        signals::install();
+        term
     }
 
-    pub fn run<T: Game>(&mut self, game: &mut T) {
-        self.backend.set_input_mode(InputMode::Character);
-        self.backend.set_input_timeout(TimeoutMode::Immediate);
-        self.backend
-            .set_cursor_visibility(CursorVisibility::Invisible);
-        self.backend.set_keypad_enabled(true);
-        self.backend.set_echo(false);
+    /// Turn reporting of mouse buttons, wheel and motion on or off. It's on from the
+    /// start, but can be turned off to let the terminal select text as usual.
+    pub fn set_mouse_reporting(&mut self, enabled: bool) {
+        // Any motion tracking, with SGR (1006) encoding so large terminals work
+        self.mouse_reporting = enabled;
+        let mode = if enabled { 'h' } else { 'l' };
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\x1b[?1003{}\x1b[?1006{}", mode, mode);
+        let _ = stdout.flush();
+    }
 
-        let frame_target_duration = Duration::new(1, 0).checked_div(60).unwrap();
+    /// Turn reporting of the terminal gaining and losing focus on or off.
+    fn set_focus_reporting(&mut self, enabled: bool) {
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\x1b[?1004{}", if enabled { 'h' } else { 'l' });
+        let _ = stdout.flush();
+    }
 
-        let (mut h, mut w) = self.backend.get_row_col_count();
-        let (mut cx, mut cy) = (w as f32 / 2., h as f32 / 2.);
+    /// Turn kitty's keyboard protocol on or off, so terminals supporting it report key
+    /// releases. It's off from the start. Terminals without it ignore the request, and
+    /// keys are taken to be released once they stop repeating, as usual;
+    /// `InputState::reports_releases` says which happened.
+    ///
+    /// The terminal then sends Ctrl-C and Ctrl-Z as keys instead of signals, and
+    /// `poll_input` quits and suspends on them itself.
+    pub fn set_kitty_keyboard(&mut self, enabled: bool) {
+        if signals::KITTY_KEYBOARD.swap(enabled, Ordering::SeqCst) == enabled {
+            return;
+        }
 
-        let mut delta_time: f32 = 0.;
+        let mut stdout = io::stdout();
+        if enabled {
+            // Ask for keys to be disambiguated, with releases, shifted keys and every key
+            // reported as a sequence, then for whether that worked. Terminals which
+            // don't understand still answer the query for device attributes after it.
+            let _ = write!(stdout, "\x1b[>15u\x1b[?u\x1b[c");
+        } else {
+            let _ = write!(stdout, "\x1b[<u");
+            self.input.set_reports_releases(false);
+        }
+        let _ = stdout.flush();
+    }
 
+    /// Run a game until `quit_key` is pressed or `Term3D::quit` is called. This is built
+    /// on the same steps as are available for driving the engine from another loop; see
+    /// `Term3D::poll_input`.
+    pub fn run<T: Game>(&mut self, game: &mut T) {
         // Initialize game
+        self.quitting = false;
         game.start(self);
 
         //self.log("Game started!", Color::Green);
         //self.log("", Color::Red);
 
+        let mut last_frame = Instant::now();
         loop {
             let top_of_loop = Instant::now();
-
-            let key = self.backend.get_input();
-            if key == Some(Input::Character('\u{1b}')) {
+            // The whole time since the last frame started, including its sleep and refresh
+            let delta_time = top_of_loop.duration_since(last_frame).as_secs_f32();
+            last_frame = top_of_loop;
+
+            self.poll_input();
+            if let Some(key) = self.quit_key {
+                if self.input.events().contains(&key) {
+                    self.quit();
+                }
+            }
+            if self.quitting {
                 break;
-            } else if key == Some(Input::KeyResize) {
-                self.backend.resize(0, 0);
-                let (height, width) = self.backend.get_row_col_count();
-                w = width;
-                h = height;
-                cx = w as f32 / 2.;
-                cy = h as f32 / 2.;
             }
-
-            // clear screen
-            self.backend.set_color_pair(ColorPair::default());
-            for x in 0..w {
-                for y in 0..h {
-                    draw_cell(&mut self.backend, ' ', x, y);
-                }
+            if self.input.resized() {
+                let (w, h) = self.get_dimensions();
+                game.on_resize(self, w, h);
+            }
+            if self.input.focus_changed() {
+                game.on_focus_change(self, self.input.focused());
             }
 
-            game.update(self, delta_time, key);
-
-            // if self.log.len() >= 2 {
-            //     self.log[0].0 = format!("{}", self.log[1].2.as_secs());
-            // }
-
-            //let after_updates = Instant::now();
-
-            let mut face_list = Vec::<([IVec2; 4], Option<Color>)>::new(); // All faces that will be rendered onto the screen
-                                                                           //let mut face_color = Vec::<Color>::new(); // Colors in the same length and order as face_list
-            let mut depth = Vec::<f32>::new(); // Face's distances from the camera
-
-            for obj in &self.objects {
-                // Vertices after mutation by camera position and rotation,
-                // and object position offset.
-                let mut vert_list = Vec::<[f32; 3]>::new();
-                // Position of the vertices in vert_list as screen
-                // coordinates.
-                let mut screen_coords = Vec::<IVec2>::new();
-
-                for vert in &obj.mesh.verts {
-                    let (x, y, z) = (
-                        vert[0] - self.cam.transform.pos.0,
-                        vert[1] / 2. - self.cam.transform.pos.1,
-                        vert[2] - self.cam.transform.pos.2,
-                    );
-                    let (mut x, z) = rotate_2d((x, z), self.cam.transform.rot.1);
-                    let (mut y, z) = rotate_2d((y, z), self.cam.transform.rot.0);
-                    vert_list.push([x, y, z]);
-
-                    let f = 200. / z;
-                    x *= f;
-                    y *= f;
-                    screen_coords.push(IVec2::new((cx + x) as i32, (cy + y) as i32));
-                }
+            self.clear();
+            self.run_fixed_updates(game, delta_time);
+            game.update(self, delta_time);
+            self.advance(delta_time);
+            game.pre_render(self);
+            self.render_frame();
+            game.draw_overlay(self);
+            self.present();
+            if self.quitting {
+                break;
+            }
 
-                for i in 0..obj.mesh.faces.len() {
-                    let face = obj.mesh.faces[i];
-
-                    let mut on_screen = false;
-                    for &i in &face.0 {
-                        let p = screen_coords[i as usize];
-                        // If any of the face's corners are within view
-                        if vert_list[i as usize][2] > 0. && (p.x >= 0 && p.x <= w) || (p.y >= 0 && p.y <= h)
-                        {
-                            on_screen = true;
-                            break; // Break from the iteration
-                        }
-                    }
-
-                    if on_screen {
-                        face_list.push((
-                            //face.0.iter().map(|&v| screen_coords[v as usize]).collect(),
-                            [
-                                screen_coords[face.0[0] as usize],
-                                screen_coords[face.0[1] as usize],
-                                screen_coords[face.0[2] as usize],
-                                screen_coords[face.0[3] as usize],
-                            ],
-                            face.1,
-                        ));
-                        //face_color.push(COLORS[i]);
-
-                        // depth += [sum(sum(vert_list[j][k] for j in face)**2 for k in range(3))]
-                        depth.push(
-                            (0..3)
-                                .map(|k| {
-                                    face.0
-                                        .iter()
-                                        .map(|&j| vert_list[j as usize][k as usize])
-                                        .sum::<f32>()
-                                        .powi(2)
-                                })
-                                .sum::<f32>(),
-                        );
-                    }
+            // Sleep the remainder of the target frame rate time
+            if let Some(fps) = self.target_fps.filter(|&fps| fps > 0) {
+                let frame_target_duration = Duration::from_secs(1) / fps;
+                if let Some(frame_remaining) = frame_target_duration.checked_sub(top_of_loop.elapsed()) {
+                    sleep(frame_remaining);
                 }
             }
+        }
 
-            let mut order = (0..face_list.len()).collect::<Vec<usize>>();
-            order.sort_by_key(|&k| NotNan::new(depth[k]).unwrap());
-            order.reverse();
-
-            for i in order {
-                draw_quad(
-                    &mut self.backend,
-                    ColorPair::new(
-                        match face_list[i].1 {
-                            Some(c) => c,
-                            None => Color::White,
-                        },
-                        Color::Black,
-                    ),
-                    face_list[i].0[0],
-                    face_list[i].0[1],
-                    face_list[i].0[2],
-                    face_list[i].0[3],
-                );
-            }
+        game.on_exit(self);
+    }
 
-            if !self.log.is_empty() {
-                let mut to_be_removed = Vec::<usize>::new();
+    /// Ask `run` to stop once the current frame has been drawn. Loops of your own can
+    /// check for this with `Term3D::quit_requested`.
+    pub fn quit(&mut self) {
+        self.quitting = true;
+    }
 
-                for i in 0..self.log.len() {
-                    // Draw log text
-                    self.backend.move_rc(i as i32, 0);
-                    self.backend.set_color_pair(ColorPair::new(self.log[i].1, Color::Black));
-                    self.backend.print(&self.log[i].0);
+    /// Whether `Term3D::quit` has been called.
+    pub fn quit_requested(&self) -> bool {
+        self.quitting
+    }
 
-                    match self.log[i].2.checked_sub(Duration::from_millis((delta_time * 1000.) as u64)) {
-                        None => to_be_removed.push(i),
-                        Some(v) => self.log[i].2 = v,
-                    }
-                }
+    /// Take everything the terminal has reported since the last call into `input`,
+    /// without waiting. Terminal resizes are handled here, as are Ctrl-C, which asks to
+    /// quit, and Ctrl-Z, which suspends the game until it's brought back.
+    ///
+    /// This is the first of the steps making up a frame, for embedding the engine in a
+    /// loop of your own rather than calling `run`:
+    ///
+    /// ```ignore
+    /// term.poll_input();
+    /// term.clear();
+    /// term.run_fixed_updates(&mut game, delta);
+    /// game.update(&mut term, delta);
+    /// term.advance(delta);
+    /// game.pre_render(&mut term);
+    /// term.render_frame();
+    /// game.draw_overlay(&mut term);
+    /// term.present();
+    /// ```
+    pub fn poll_input(&mut self) {
+        if signals::take(&signals::QUIT) {
+            self.quit();
+        }
+        if signals::take(&signals::SUSPEND) {
+            self.suspend();
+        }
+        if signals::take(&signals::RESUMED) {
+            // The screen may have been drawn over while stopped, so redraw all of it
+            self.backend.win.clearok(true);
+        }
 
-                if !to_be_removed.is_empty() { // TODO: Bug exists somewhere in this block of code
-                    let mut offset = 0;
-                    for index in to_be_removed {
-                        // This only works because the items in to_be_removed are added
-                        // in the same order as 0..self.log.len() (they are sorted)
-                        self.log.remove(max(0, index - offset));
-                        offset += 1;
-                    }
+        let now = Instant::now();
+        self.input.begin_frame();
+        let mut events = Vec::new();
+        while let Some(key) = self.backend.get_input() {
+            if key == Input::KeyResize {
+                self.backend.resize(0, 0);
+            }
+            self.decoder.push(key, &mut events);
+        }
+        // The rest of a sequence split between reads may still be on its way
+        self.decoder.flush_stale(Instant::now(), &mut events);
+
+        for event in events {
+            match event {
+                Event::Key(key) => self.input.push(key, now),
+                Event::Keyboard(key) => match (key.action, key.typed()) {
+                    // Kitty's protocol sends these as keys, so the terminal no longer
+                    // raises the signals they would have
+                    (KeyAction::Press, Input::Character('\u{3}')) => self.quit(),
+                    (KeyAction::Press, Input::Character('\u{1a}')) => self.suspend(),
+                    _ => self.input.push_key(key, now),
+                },
+                Event::Mouse(mouse) => self.input.push_mouse(mouse),
+                Event::KeyboardFlags(flags) => {
+                    // Releases are only reported while kitty's protocol is still on
+                    let on = signals::KITTY_KEYBOARD.load(Ordering::SeqCst);
+                    self.input.set_reports_releases(on && flags & 2 != 0)
                 }
+                Event::DeviceAttributes => {}
+                Event::Focus(focused) => self.input.set_focus(focused),
             }
+        }
+        self.input.release_stale(now);
+        self.update_console();
+        self.log.handle_input(&self.input);
+    }
 
-            let elapsed_this_frame = top_of_loop.elapsed();
-            // Sleep the remainder of the target frame rate time
-            if let Some(frame_remaining) = frame_target_duration.checked_sub(elapsed_this_frame) {
-                sleep(frame_remaining);
+    /// Give the terminal back to the shell and stop, as Ctrl-Z normally would, until
+    /// carried on. Curses takes the terminal back on the next refresh.
+    fn suspend(&mut self) {
+        let mouse = self.mouse_reporting;
+        let kitty = signals::KITTY_KEYBOARD.load(Ordering::SeqCst);
+        self.set_mouse_reporting(false);
+        self.set_focus_reporting(false);
+        self.set_kitty_keyboard(false);
+        pancurses::endwin();
+
+        signals::stop();
+
+        self.set_mouse_reporting(mouse);
+        self.set_focus_reporting(true);
+        self.set_kitty_keyboard(kitty);
+    }
+
+    /// Call `Game::fixed_update` as many times as `fixed_timestep` fits into the time
+    /// passed, carrying over the remainder to later frames.
+    pub fn run_fixed_updates<T: Game>(&mut self, game: &mut T, delta: f32) {
+        if let Some(step) = self.fixed_timestep.filter(|&step| step > 0.) {
+            self.accumulator += delta.min(MAX_FIXED_CATCH_UP);
+            while self.accumulator >= step {
+                game.fixed_update(self, step);
+                self.accumulator -= step;
             }
+        }
+    }
 
-            self.backend.refresh();
+    /// Move time along for the engine: camera paths, and how long log lines have left.
+    pub fn advance(&mut self, delta: f32) {
+        for cam in &mut self.cameras {
+            cam.advance_path(delta);
+        }
+        self.log.take_records();
+        self.log.advance(delta);
+    }
 
-            //let elapsed_after_updates = after_updates.elapsed();
-            //delta_time = (elapsed_after_updates.as_secs() as f32)
-            //    + ((elapsed_after_updates.subsec_nanos() as f32) / 1000000000.0);
-            delta_time = elapsed_this_frame.subsec_nanos() as f32 / 1000000000.0;
+    /// Draw the scene from every camera, then the log or console over it.
+    pub fn render_frame(&mut self) {
+        self.render_scene();
+        if self.console.is_open() {
+            self.console.draw(&mut self.backend, &self.log);
+        } else {
+            self.log.draw(&mut self.backend);
         }
     }
 
-    pub fn log(&mut self, text: &str, color: Color) {
-        self.log.insert(0, (text.to_owned(), color, Duration::from_secs(10)));
-        if self.log.len() > self.backend.get_row_col_count().0 as usize {
-            self.log.pop();
+    /// Show everything drawn since the last call on the terminal.
+    pub fn present(&mut self) {
+        self.backend.refresh();
+    }
+
+    /// How far the current frame is between the last fixed update and the next, from 0
+    /// to 1. Use it to interpolate what's drawn between fixed update states.
+    pub fn alpha(&self) -> f32 {
+        match self.fixed_timestep {
+            Some(step) if step > 0. => self.accumulator / step,
+            _ => 0.,
         }
     }
+
+    /// Add a line to the log in a color. `term.log.warn(text)` and friends log at other
+    /// levels.
+    pub fn log(&mut self, text: &str, color: Color) {
+        self.log.push_colored(Level::Info, color, text);
+    }
+}
+
+impl Drop for Term3D {
+    fn drop(&mut self) {
+        // Curses restores the rest of the terminal when the backend is dropped
+        self.set_mouse_reporting(false);
+        self.set_focus_reporting(false);
+        self.set_kitty_keyboard(false);
+    }
 }
 
 pub trait Render {
@@ -0,0 +1,393 @@
+//! The log drawn over the game: recent lines fade out after a while, and every line
+//! is kept in a scrollback which can be opened with a key.
+//!
+//! Anything logged through the `log` crate, by the game or its dependencies, can be
+//! shown here too once a `Logger` is installed, rather than being printed over the
+//! screen:
+//!
+//! ```ignore
+//! Logger::new(log::LevelFilter::Info).file("game.log")?.install()?;
+//! ```
+
+use crate::input::InputState;
+
+use easycurses::{Color, ColorPair, EasyCurses, Input};
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The most records kept waiting for a `Term3D` to take them. The oldest are dropped
+/// to make room for new ones.
+const MAX_QUEUED: usize = 1000;
+
+/// Records from the `log` crate, waiting to be added to a `Term3D`'s log. Made when the
+/// first record arrives.
+static QUEUE: Mutex<Option<VecDeque<(Level, String)>>> = Mutex::new(None);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn from_log(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warn,
+            log::Level::Info => Level::Info,
+            log::Level::Debug => Level::Debug,
+            log::Level::Trace => Level::Trace,
+        }
+    }
+
+    /// The color lines of this level are drawn in.
+    pub fn color(self) -> Color {
+        match self {
+            Level::Trace => Color::Blue,
+            Level::Debug => Color::Cyan,
+            Level::Info => Color::White,
+            Level::Warn => Color::Yellow,
+            Level::Error => Color::Red,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub level: Level,
+    pub color: Color,
+    pub text: String,
+    /// When the line was logged, since the log was made.
+    pub time: Duration,
+    /// Seconds the line has been shown over the game for.
+    age: f32,
+}
+
+pub struct Log {
+    /// Lines, oldest first.
+    lines: VecDeque<Line>,
+    /// The most lines kept. The oldest are dropped to make room for new ones.
+    pub capacity: usize,
+    /// Seconds new lines are shown over the game for.
+    pub lifetime: f32,
+    /// Lines below this level are ignored.
+    pub min_level: Level,
+    /// Start lines with the time they were logged.
+    pub timestamps: bool,
+    /// The key which opens and closes the scrollback, or `None` for no key.
+    pub toggle_key: Option<Input>,
+    open: bool,
+    /// Lines scrolled back from the newest, while the scrollback is open.
+    scroll: usize,
+    start: Instant,
+}
+
+impl Log {
+    pub fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity: 1000,
+            lifetime: 10.,
+            min_level: Level::Trace,
+            timestamps: false,
+            toggle_key: Some(Input::KeyF12),
+            open: false,
+            scroll: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Add a line in the color of its level.
+    pub fn push(&mut self, level: Level, text: &str) {
+        self.push_colored(level, level.color(), text);
+    }
+
+    /// Add a line in a color of its own.
+    pub fn push_colored(&mut self, level: Level, color: Color, text: &str) {
+        if level < self.min_level || self.capacity == 0 {
+            return;
+        }
+
+        let time = self.start.elapsed();
+        for text in text.lines() {
+            while self.lines.len() >= self.capacity {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(Line {
+                level,
+                color,
+                text: text.to_owned(),
+                time,
+                age: 0.,
+            });
+            // Keep the scrollback still while new lines arrive
+            if self.scroll > 0 {
+                self.scroll += 1;
+            }
+        }
+    }
+
+    pub fn trace(&mut self, text: &str) {
+        self.push(Level::Trace, text);
+    }
+
+    pub fn debug(&mut self, text: &str) {
+        self.push(Level::Debug, text);
+    }
+
+    pub fn info(&mut self, text: &str) {
+        self.push(Level::Info, text);
+    }
+
+    pub fn warn(&mut self, text: &str) {
+        self.push(Level::Warn, text);
+    }
+
+    pub fn error(&mut self, text: &str) {
+        self.push(Level::Error, text);
+    }
+
+    /// Every line kept, oldest first.
+    pub fn lines(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.scroll = 0;
+    }
+
+    /// Whether the scrollback is open.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Open or close the scrollback, scrolled to the newest line.
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+        self.scroll = 0;
+    }
+
+    /// Add the records logged through the `log` crate since the last call.
+    pub(crate) fn take_records(&mut self) {
+        let records = match QUEUE.lock() {
+            Ok(mut queue) => queue.take().unwrap_or_default(),
+            Err(_) => return,
+        };
+        for (level, text) in records {
+            self.push(level, &text);
+        }
+    }
+
+    /// Age the lines shown over the game.
+    pub(crate) fn advance(&mut self, delta: f32) {
+        // Lines are only aged until they expire, and never removed, so they stay in the
+        // scrollback
+        for line in self.lines.iter_mut().rev() {
+            if line.age >= self.lifetime {
+                break;
+            }
+            line.age += delta;
+        }
+    }
+
+    /// Open and close the scrollback with the toggle key, and scroll it with Page Up,
+    /// Page Down, Home, End and the mouse wheel.
+    pub(crate) fn handle_input(&mut self, input: &InputState) {
+        for &event in input.events() {
+            if Some(event) == self.toggle_key {
+                self.set_open(!self.open);
+                continue;
+            }
+            if !self.open {
+                continue;
+            }
+            match event {
+                Input::KeyHome => self.scroll = self.lines.len(),
+                Input::KeyEnd => self.scroll = 0,
+                _ => {}
+            }
+        }
+        if self.open {
+            self.scroll_with(input);
+        }
+        self.scroll = self.scroll.min(self.lines.len().saturating_sub(1));
+    }
+
+    /// Scroll back with Page Up and the mouse wheel, and forward with Page Down.
+    pub(crate) fn scroll_with(&mut self, input: &InputState) {
+        let mut lines = input.scroll() as i64 * 3;
+        for &event in input.events() {
+            match event {
+                Input::KeyPPage => lines += 10,
+                Input::KeyNPage => lines -= 10,
+                _ => {}
+            }
+        }
+        let max = self.lines.len().saturating_sub(1) as i64;
+        self.scroll = (self.scroll as i64 + lines).max(0).min(max) as usize;
+    }
+
+    /// Draw the scrollback over the top half of the screen if it's open, or otherwise
+    /// the lines which haven't expired yet, newest first.
+    pub(crate) fn draw(&self, backend: &mut EasyCurses) {
+        let (h, w) = backend.get_row_col_count();
+        if h <= 0 {
+            return;
+        }
+        if self.open {
+            let rows = panel_rows(h);
+            let end = self.draw_history(backend, rows - 1); // Leaving a row for the status line
+
+            let status = format!(
+                "-- {} of {} lines -- PgUp/PgDn to scroll --",
+                end,
+                self.lines.len()
+            );
+            backend.set_color_pair(ColorPair::new(Color::Black, Color::White));
+            backend.move_rc(rows as i32 - 1, 0);
+            backend.print(format!("{:1$}", status, w.max(0) as usize));
+        } else {
+            let recent = self.lines.iter().rev().take_while(|line| line.age < self.lifetime);
+            for (y, line) in recent.take(h.max(0) as usize).enumerate() {
+                self.draw_line(backend, line, y as i32, w);
+            }
+        }
+    }
+
+    /// Draw the newest lines, less any scrolled back, over the top `rows` rows of the
+    /// screen. Returns how many lines there are up to the last one drawn.
+    pub(crate) fn draw_history(&self, backend: &mut EasyCurses, rows: usize) -> usize {
+        let w = backend.get_row_col_count().1;
+        let end = self.lines.len() - self.scroll.min(self.lines.len());
+        let start = end.saturating_sub(rows);
+
+        backend.set_color_pair(ColorPair::new(Color::White, Color::Black));
+        for y in 0..rows as i32 {
+            backend.move_rc(y, 0);
+            backend.print(" ".repeat(w.max(0) as usize));
+        }
+        for (y, line) in self.lines.range(start..end).enumerate() {
+            self.draw_line(backend, line, y as i32, w);
+        }
+        end
+    }
+
+    fn draw_line(&self, backend: &mut EasyCurses, line: &Line, y: i32, width: i32) {
+        let mut text = if self.timestamps {
+            format!("[{}] {}", format_time(line.time), line.text)
+        } else {
+            line.text.clone()
+        };
+        if let Some((end, _)) = text.char_indices().nth(width.max(0) as usize) {
+            text.truncate(end);
+        }
+
+        backend.set_color_pair(ColorPair::new(line.color, Color::Black));
+        backend.move_rc(y, 0);
+        backend.print(text);
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A time as minutes, seconds and milliseconds, like `01:23.456`.
+fn format_time(time: Duration) -> String {
+    let (secs, millis) = (time.as_secs(), time.subsec_millis());
+    format!("{:02}:{:02}.{:03}", secs / 60, secs % 60, millis)
+}
+
+/// How many rows of a screen `height` rows high a drop-down panel covers.
+pub(crate) fn panel_rows(height: i32) -> usize {
+    (height / 2).max(3).min(height).max(0) as usize
+}
+
+/// A `log::Log` which sends records to the log drawn over the game, and optionally
+/// appends them to a file.
+pub struct Logger {
+    level: log::LevelFilter,
+    file: Option<Mutex<File>>,
+    start: Instant,
+}
+
+impl Logger {
+    /// Log records up to `level`.
+    pub fn new(level: log::LevelFilter) -> Self {
+        Self {
+            level,
+            file: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Append records to a file as well, creating it if needed. Each starts with the
+    /// time since the logger was made.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    /// Make this the logger for the `log` crate. Fails if one has already been set.
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        let level = self.level;
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let text = format!("{}: {}", record.target(), record.args());
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let time = format_time(self.start.elapsed());
+                let _ = writeln!(file, "[{}] {:<5} {}", time, record.level(), text);
+            }
+        }
+        if let Ok(mut queue) = QUEUE.lock() {
+            let queue = queue.get_or_insert_with(VecDeque::new);
+            if queue.len() >= MAX_QUEUED {
+                queue.pop_front();
+            }
+            queue.push_back((Level::from_log(record.level()), text));
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Smooth camera movement along splines, for intros, cutscenes and replays.
+//!
+//! A `CameraPath` is a list of keyframes giving where the camera should be and what
+//! it should look at, at a given time. Once given to `Camera::play`, the camera follows
+//! the path by itself as frames are rendered, while `Game::update` keeps running.
+
+use crate::Camera;
+
+/// Shapes the progress through a segment of a path, so movement can speed up and slow
+/// down around keyframes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// Start slowly.
+    EaseIn,
+    /// Stop slowly.
+    EaseOut,
+    /// Start and stop slowly.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map progress `t` from 0 to 1 onto eased progress from 0 to 1.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// How the keyframes of a path are joined together.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Curve {
+    /// A curve passing through every keyframe.
+    CatmullRom,
+    /// Cubic Bézier segments. Keyframes 0, 3, 6 and so on are passed through, and the
+    /// two keyframes between each pair of those only pull the curve towards them. The
+    /// times and easing of those in-between keyframes are ignored.
+    Bezier,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe {
+    /// Seconds since the start of the path.
+    pub time: f32,
+    /// Where the camera is, in world space.
+    pub pos: [f32; 3],
+    /// What the camera is looking at, in world space.
+    pub target: [f32; 3],
+    /// Easing of the segment from this keyframe to the next.
+    pub easing: Easing,
+}
+
+pub struct CameraPath {
+    /// Keyframes, in increasing order of time.
+    pub keys: Vec<Keyframe>,
+    pub curve: Curve,
+    /// Start again from the beginning once the end is reached.
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn new(curve: Curve) -> Self {
+        Self {
+            keys: Vec::new(),
+            curve,
+            looping: false,
+        }
+    }
+
+    /// Add a keyframe to the end of the path.
+    pub fn push(&mut self, time: f32, pos: [f32; 3], target: [f32; 3], easing: Easing) {
+        self.keys.push(Keyframe {
+            time,
+            pos,
+            target,
+            easing,
+        });
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0., |key| key.time)
+    }
+
+    /// The camera position and look target at a time along the path, both in world space.
+    /// Times outside the path are clamped to its ends. Returns `None` for an empty path.
+    pub fn sample(&self, time: f32) -> Option<([f32; 3], [f32; 3])> {
+        let keys = &self.keys;
+        if keys.is_empty() {
+            return None;
+        }
+
+        let stride = match self.curve {
+            Curve::CatmullRom => 1,
+            Curve::Bezier => 3,
+        };
+        // The last key the curve passes through
+        let end = (keys.len() - 1) / stride * stride;
+
+        if time <= keys[0].time || end == 0 {
+            return Some((keys[0].pos, keys[0].target));
+        } else if time >= keys[end].time {
+            return Some((keys[end].pos, keys[end].target));
+        }
+
+        // Find the segment containing the time, and how far through it the time is
+        let mut i = 0;
+        while keys[i + stride].time <= time {
+            i += stride;
+        }
+        let (start, finish) = (keys[i].time, keys[i + stride].time);
+        let t = keys[i].easing.apply((time - start) / (finish - start));
+
+        Some(match self.curve {
+            Curve::CatmullRom => {
+                let p = [
+                    &keys[i.saturating_sub(1)],
+                    &keys[i],
+                    &keys[i + 1],
+                    &keys[(i + 2).min(keys.len() - 1)],
+                ];
+                (
+                    catmull_rom([p[0].pos, p[1].pos, p[2].pos, p[3].pos], t),
+                    catmull_rom([p[0].target, p[1].target, p[2].target, p[3].target], t),
+                )
+            }
+            Curve::Bezier => {
+                let p = &keys[i..=i + 3];
+                (
+                    bezier([p[0].pos, p[1].pos, p[2].pos, p[3].pos], t),
+                    bezier([p[0].target, p[1].target, p[2].target, p[3].target], t),
+                )
+            }
+        })
+    }
+}
+
+/// A point on the segment between `p[1]` and `p[2]` of a uniform Catmull-Rom spline.
+fn catmull_rom(p: [[f32; 3]; 4], t: f32) -> [f32; 3] {
+    let mut out = [0.; 3];
+    for k in 0..3 {
+        let (p0, p1, p2, p3) = (p[0][k], p[1][k], p[2][k], p[3][k]);
+        out[k] = 0.5
+            * (2. * p1
+                + (p2 - p0) * t
+                + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t * t
+                + (3. * p1 - p0 - 3. * p2 + p3) * t * t * t);
+    }
+    out
+}
+
+/// A point on a cubic Bézier curve.
+fn bezier(p: [[f32; 3]; 4], t: f32) -> [f32; 3] {
+    let u = 1. - t;
+    let mut out = [0.; 3];
+    for k in 0..3 {
+        out[k] = u * u * u * p[0][k]
+            + 3. * u * u * t * p[1][k]
+            + 3. * u * t * t * p[2][k]
+            + t * t * t * p[3][k];
+    }
+    out
+}
+
+/// A path being followed by a camera.
+pub struct PathPlayback {
+    pub path: CameraPath,
+    /// Seconds since the path was started.
+    pub time: f32,
+}
+
+impl Camera {
+    /// Start following a path from its beginning, replacing any path already playing.
+    pub fn play(&mut self, path: CameraPath) {
+        self.path = Some(PathPlayback { path, time: 0. });
+    }
+
+    /// Move along the path being played by `delta` seconds. The path is dropped once
+    /// its end is reached, unless it loops.
+    pub(crate) fn advance_path(&mut self, delta: f32) {
+        let playback = match &mut self.path {
+            Some(playback) => playback,
+            None => return,
+        };
+
+        playback.time += delta;
+        let duration = playback.path.duration();
+        let finished = playback.time >= duration && !playback.path.looping;
+        if playback.path.looping && duration > 0. {
+            playback.time %= duration;
+        }
+
+        if let Some((pos, target)) = playback.path.sample(playback.time) {
+            self.set_world_position(pos);
+            self.look_at(target);
+        }
+
+        if finished {
+            self.path = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+        for k in 0..3 {
+            assert!((actual[k] - expected[k]).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    fn path_through(curve: Curve, keys: &[(f32, [f32; 3])]) -> CameraPath {
+        let mut path = CameraPath::new(curve);
+        for &(time, pos) in keys {
+            // Look at a point offset from the camera, so targets are checked too
+            let target = [pos[0], pos[1], pos[2] + 10.];
+            path.push(time, pos, target, Easing::Linear);
+        }
+        path
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_keyframes() {
+        let keys = [
+            (0., [0., 0., 0.]),
+            (1., [4., 2., 0.]),
+            (3., [4., -2., 6.]),
+            (4., [-1., 0., 3.]),
+        ];
+        let path = path_through(Curve::CatmullRom, &keys);
+        for &(time, pos) in &keys {
+            let (p, target) = path.sample(time).unwrap();
+            assert_near(p, pos);
+            assert_near(target, [pos[0], pos[1], pos[2] + 10.]);
+        }
+
+        // Curves past a straight line between keyframes
+        let (p, _) = path.sample(2.).unwrap();
+        assert!(p[0] > 4.);
+    }
+
+    #[test]
+    fn bezier_passes_through_every_third_keyframe() {
+        let p0 = [0., 0., 0.];
+        let (p1, p2) = ([0., 8., 0.], [8., 8., 0.]);
+        let p3 = [8., 0., 0.];
+        // The times of control points are ignored, and a trailing key which doesn't
+        // finish a segment is never reached
+        let path = path_through(
+            Curve::Bezier,
+            &[(0., p0), (100., p1), (-5., p2), (2., p3), (3., [50., 50., 50.])],
+        );
+
+        assert_near(path.sample(0.).unwrap().0, p0);
+        assert_near(path.sample(1.).unwrap().0, [4., 6., 0.]);
+        assert_near(path.sample(2.).unwrap().0, p3);
+        assert_near(path.sample(2.5).unwrap().0, p3);
+
+        // Too few keys for a segment
+        let path = path_through(Curve::Bezier, &[(0., p0), (1., p1), (2., p2)]);
+        assert_near(path.sample(1.5).unwrap().0, p0);
+    }
+
+    #[test]
+    fn clamps_outside_the_path() {
+        let (first, last) = ([1., 2., 3.], [4., 5., 6.]);
+        for &curve in &[Curve::CatmullRom, Curve::Bezier] {
+            let path = path_through(curve, &[(1., first), (2., [0.; 3]), (3., [0.; 3]), (4., last)]);
+            assert_near(path.sample(-10.).unwrap().0, first);
+            assert_near(path.sample(0.5).unwrap().0, first);
+            assert_near(path.sample(4.5).unwrap().0, last);
+        }
+        assert_eq!(CameraPath::new(Curve::CatmullRom).sample(0.), None);
+    }
+
+    #[test]
+    fn easing_keeps_the_ends() {
+        for &easing in &[Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.), 0.);
+            assert_eq!(easing.apply(1.), 1.);
+        }
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    }
+
+    #[test]
+    fn looping_paths_wrap_around() {
+        let keys = [(0., [0., 0., 0.]), (2., [10., 4., 0.])];
+        let mut cam = Camera::new((0., 0., 0.), (0., 0.));
+        let mut path = path_through(Curve::CatmullRom, &keys);
+        path.looping = true;
+        let halfway = path.sample(0.5).unwrap().0;
+        cam.play(path);
+
+        cam.advance_path(1.5);
+        cam.advance_path(1.);
+        let playback = cam.path.as_ref().expect("looping paths keep playing");
+        assert!((playback.time - 0.5).abs() < 1e-4);
+        assert_near(cam.world_position(), halfway);
+    }
+
+    #[test]
+    fn finished_paths_stop_at_the_end() {
+        let keys = [(0., [0., 0., 0.]), (2., [10., 4., 0.])];
+        let mut cam = Camera::new((0., 0., 0.), (0., 0.));
+        cam.play(path_through(Curve::CatmullRom, &keys));
+
+        cam.advance_path(1.);
+        assert!(cam.path.is_some());
+        cam.advance_path(1.5);
+        assert!(cam.path.is_none());
+        assert_near(cam.world_position(), [10., 4., 0.]);
+    }
+}
@@ -0,0 +1,170 @@
+//! Tile-binned rasterisation of a frame's triangles, spread over several threads.
+//!
+//! The framebuffer is split into fixed size tiles. Every triangle is binned into the
+//! tiles its bounding box touches, then each tile is rasterised on its own by one of
+//! the framebuffer's pool of worker threads, which live as long as it does.
+//! Triangles within a tile are drawn in submission order, so the painter's algorithm
+//! used by `Term3D::run` still holds.
+
+use crate::core::rasterize_tri;
+use crate::glm::Vec2;
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use std::cmp::{max, min};
+
+/// Width of a tile in cells.
+pub const TILE_WIDTH: i32 = 32;
+/// Height of a tile in cells. Terminal cells are roughly twice as tall as they are
+/// wide, so this keeps tiles close to square on screen.
+pub const TILE_HEIGHT: i32 = 16;
+
+const TILE_AREA: usize = (TILE_WIDTH * TILE_HEIGHT) as usize;
+
+/// An inclusive `(minimum x, maximum x, minimum y, maximum y)` box of cells.
+pub type Clip = (i32, i32, i32, i32);
+
+/// A screen space triangle to be rasterised.
+#[derive(Copy, Clone)]
+pub struct Triangle {
+    pub verts: [Vec2; 3],
+    /// Written to every cell the triangle covers. `Term3D` uses this as an index into
+    /// the list of faces drawn this frame.
+    pub id: u32,
+}
+
+/// A grid of cells holding the id of the last triangle drawn over each of them.
+///
+/// Cells are stored tile by tile rather than row by row, so every worker thread writes
+/// to its own contiguous slice and the results need no further merging afterwards.
+pub struct Framebuffer {
+    width: i32,
+    height: i32,
+    tiles_x: i32,
+    tiles_y: i32,
+    cells: Vec<Option<u32>>,
+    bins: Vec<Vec<u32>>,
+    /// Worker threads, kept between frames. Rebuilt if the number asked for changes.
+    pool: Option<ThreadPool>,
+}
+
+impl Framebuffer {
+    pub fn new(width: i32, height: i32) -> Self {
+        let mut fb = Self {
+            width: 0,
+            height: 0,
+            tiles_x: 0,
+            tiles_y: 0,
+            cells: Vec::new(),
+            bins: Vec::new(),
+            pool: None,
+        };
+        fb.resize(width, height);
+        fb
+    }
+
+    /// Resize the framebuffer, clearing it if the size changed.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        let (width, height) = (max(0, width), max(0, height));
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.tiles_x = (width + TILE_WIDTH - 1) / TILE_WIDTH;
+        self.tiles_y = (height + TILE_HEIGHT - 1) / TILE_HEIGHT;
+        let tiles = (self.tiles_x * self.tiles_y) as usize;
+        self.cells = vec![None; tiles * TILE_AREA];
+        self.bins.resize_with(tiles, Vec::new);
+    }
+
+    /// Return width and height in cells.
+    pub fn get_dimensions(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// The id of the triangle covering a cell, if any.
+    pub fn get(&self, x: i32, y: i32) -> Option<u32> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        let tile = (y / TILE_HEIGHT * self.tiles_x + x / TILE_WIDTH) as usize;
+        let local = (y % TILE_HEIGHT * TILE_WIDTH + x % TILE_WIDTH) as usize;
+        self.cells[tile * TILE_AREA + local]
+    }
+
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            *cell = None;
+        }
+    }
+
+    /// Rasterise `tris` in order over the current contents, using up to `threads`
+    /// threads. Each triangle is clipped to its own box as well as the framebuffer.
+    pub fn draw(&mut self, tris: &[(Triangle, Clip)], threads: usize) {
+        for bin in &mut self.bins {
+            bin.clear();
+        }
+
+        // Bin every triangle into the tiles its clipped bounding box overlaps
+        for (i, (tri, clip)) in tris.iter().enumerate() {
+            let [a, b, c] = tri.verts;
+            let minx = max(clip.0, a.x.min(b.x).min(c.x).floor() as i32);
+            let maxx = min(min(clip.1, self.width - 1), a.x.max(b.x).max(c.x).floor() as i32);
+            let miny = max(clip.2, a.y.min(b.y).min(c.y).floor() as i32);
+            let maxy = min(min(clip.3, self.height - 1), a.y.max(b.y).max(c.y).floor() as i32);
+            if minx > maxx || miny > maxy {
+                continue;
+            }
+
+            for ty in max(0, miny) / TILE_HEIGHT..=maxy / TILE_HEIGHT {
+                for tx in max(0, minx) / TILE_WIDTH..=maxx / TILE_WIDTH {
+                    self.bins[(ty * self.tiles_x + tx) as usize].push(i as u32);
+                }
+            }
+        }
+
+        let tiles_x = self.tiles_x;
+        let draw_tile = |index: usize, cells: &mut [Option<u32>], bin: &[u32]| {
+            let left = index as i32 % tiles_x * TILE_WIDTH;
+            let top = index as i32 / tiles_x * TILE_HEIGHT;
+            for &i in bin {
+                let (tri, clip) = &tris[i as usize];
+                let tile_clip = (
+                    max(clip.0, left),
+                    min(clip.1, left + TILE_WIDTH - 1),
+                    max(clip.2, top),
+                    min(clip.3, top + TILE_HEIGHT - 1),
+                );
+                rasterize_tri(tile_clip, tri.verts[0], tri.verts[1], tri.verts[2], |x, y| {
+                    cells[((y - top) * TILE_WIDTH + x - left) as usize] = Some(tri.id);
+                });
+            }
+        };
+
+        let work = self
+            .cells
+            .chunks_mut(TILE_AREA)
+            .zip(&self.bins)
+            .enumerate()
+            .filter(|(_, (_, bin))| !bin.is_empty())
+            .collect::<Vec<_>>();
+
+        if threads > 1 && !matches!(&self.pool, Some(pool) if pool.current_num_threads() == threads) {
+            self.pool = ThreadPoolBuilder::new().num_threads(threads).build().ok();
+        }
+        match &self.pool {
+            Some(pool) if threads > 1 && work.len() > 1 => pool.install(|| {
+                work.into_par_iter().for_each(|(index, (cells, bin))| draw_tile(index, cells, bin));
+            }),
+            // A single thread, or the pool couldn't be started
+            _ => {
+                for (index, (cells, bin)) in work {
+                    draw_tile(index, cells, bin);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Finding where a ray first hits the objects in a scene.
+
+use crate::bounds::Aabb;
+use crate::glm::{self, Vec3, Vec4};
+use crate::scene::{ObjectHandle, Objects};
+use crate::Term3D;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RayHit {
+    pub object: ObjectHandle,
+    /// Index of the face hit in the object's mesh.
+    pub face: usize,
+    /// Where the face was hit, in world space.
+    pub point: [f32; 3],
+    /// The face's unit normal in world space, pointing back towards the ray's origin.
+    pub normal: [f32; 3],
+    /// Distance from the ray's origin to `point`.
+    pub distance: f32,
+}
+
+impl Term3D {
+    /// The nearest face hit by a ray in world space, no further than `max_dist` from its
+    /// origin. Every object is tested, whether visible or not.
+    pub fn raycast(&self, origin: [f32; 3], direction: [f32; 3], max_dist: f32) -> Option<RayHit> {
+        raycast(&self.objects, origin, direction, max_dist)
+    }
+}
+
+/// `Term3D::raycast` against any set of objects.
+fn raycast(objects: &Objects, origin: [f32; 3], direction: [f32; 3], max_dist: f32) -> Option<RayHit> {
+    let origin = glm::vec3(origin[0], origin[1], origin[2]);
+    let direction = glm::vec3(direction[0], direction[1], direction[2]);
+    if direction == glm::zero() {
+        return None;
+    }
+    let direction = glm::normalize(&direction);
+
+    let mut nearest: Option<RayHit> = None;
+    for (handle, obj) in objects.iter() {
+        let limit = nearest.map_or(max_dist, |hit| hit.distance);
+        let world = match objects.world_matrix(handle) {
+            Some(world) => world,
+            None => continue,
+        };
+
+        // Skip objects whose bounding sphere the ray misses
+        let sphere = obj.mesh.bounds().sphere;
+        let c = sphere.center;
+        let center = (world * Vec4::new(c[0], c[1], c[2], 1.)).xyz();
+        let along = glm::dot(&(center - origin), &direction);
+        let closest = origin + direction * along.max(0.);
+        if glm::distance2(&closest, &center) > sphere.radius * sphere.radius
+            || along - sphere.radius > limit
+        {
+            continue;
+        }
+
+        // Transforms are only rotations and translations, so distances are the same
+        // in the object's space as in world space
+        let inverse = match world.try_inverse() {
+            Some(inverse) => inverse,
+            None => continue,
+        };
+        let local_origin = (inverse * Vec4::new(origin.x, origin.y, origin.z, 1.)).xyz();
+        let local_dir = (inverse * Vec4::new(direction.x, direction.y, direction.z, 0.)).xyz();
+        if !ray_hits_aabb(&obj.mesh.bounds().aabb, &local_origin, &local_dir, limit) {
+            continue;
+        }
+
+        let vert = |i: f32| {
+            let v = obj.mesh.verts[i as usize];
+            glm::vec3(v[0], v[1], v[2])
+        };
+        for (i, face) in obj.mesh.faces.iter().enumerate() {
+            let [a, b, c, d] = face.0;
+            let (a, b, c, d) = (vert(a), vert(b), vert(c), vert(d));
+            for tri in &[(a, b, c), (a, c, d)] {
+                let t = match ray_hits_tri(&local_origin, &local_dir, tri) {
+                    Some(t) if t <= nearest.map_or(max_dist, |hit| hit.distance) => t,
+                    _ => continue,
+                };
+
+                let n = (tri.1 - tri.0).cross(&(tri.2 - tri.0));
+                let mut normal = glm::normalize(&(world * Vec4::new(n.x, n.y, n.z, 0.)).xyz());
+                if glm::dot(&normal, &direction) > 0. {
+                    normal = -normal;
+                }
+                let point = origin + direction * t;
+
+                nearest = Some(RayHit {
+                    object: handle,
+                    face: i,
+                    point: [point.x, point.y, point.z],
+                    normal: [normal.x, normal.y, normal.z],
+                    distance: t,
+                });
+            }
+        }
+    }
+    nearest
+}
+
+/// Whether a ray enters a box within `max_dist` of its origin, using the slab method.
+fn ray_hits_aabb(aabb: &Aabb, origin: &Vec3, dir: &Vec3, max_dist: f32) -> bool {
+    let (mut near, mut far) = (0f32, max_dist);
+    for k in 0..3 {
+        if dir[k] == 0. {
+            // Parallel to this pair of sides, so it must start between them
+            if origin[k] < aabb.min[k] || origin[k] > aabb.max[k] {
+                return false;
+            }
+            continue;
+        }
+
+        let t0 = (aabb.min[k] - origin[k]) / dir[k];
+        let t1 = (aabb.max[k] - origin[k]) / dir[k];
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+        if near > far {
+            return false;
+        }
+    }
+    true
+}
+
+/// The distance along a ray at which it passes through a triangle, from either side,
+/// using the Möller-Trumbore algorithm.
+fn ray_hits_tri(origin: &Vec3, dir: &Vec3, tri: &(Vec3, Vec3, Vec3)) -> Option<f32> {
+    let (a, b, c) = tri;
+    let (e1, e2) = (b - a, c - a);
+    let p = dir.cross(&e2);
+    let det = glm::dot(&e1, &p);
+    if det.abs() < f32::EPSILON {
+        return None; // Parallel to the triangle
+    }
+
+    let inv_det = 1. / det;
+    let s = origin - a;
+    let u = glm::dot(&s, &p) * inv_det;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&e1);
+    let v = glm::dot(dir, &q) * inv_det;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = glm::dot(&e2, &q) * inv_det;
+    if t >= 0. {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mesh, Object};
+
+    use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_4, SQRT_2};
+
+    fn cube_at(objects: &mut Objects, pos: (f32, f32, f32), rot: (f32, f32)) -> ObjectHandle {
+        let mut obj = Object::new(Mesh::cube());
+        obj.transform.pos = pos;
+        obj.transform.rot = rot;
+        objects.spawn(obj)
+    }
+
+    fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+        for k in 0..3 {
+            assert!((actual[k] - expected[k]).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn hits_a_transformed_cube() {
+        let mut objects = Objects::new();
+        // Turned so an edge faces the ray, which passes to one side of it
+        let cube = cube_at(&mut objects, (5., 2., 0.), (0., FRAC_PI_4));
+
+        let hit = raycast(&objects, [0., 2.5, 0.5], [1., 0., 0.], 100.).unwrap();
+        let x = 5. - SQRT_2 + 0.5;
+        assert_eq!(hit.object, cube);
+        assert_near(hit.point, [x, 2.5, 0.5]);
+        assert_near(hit.normal, [-FRAC_1_SQRT_2, 0., FRAC_1_SQRT_2]);
+        assert!((hit.distance - x).abs() < 1e-4);
+
+        // Over the top of it
+        assert_eq!(raycast(&objects, [0., 3.5, 0.], [1., 0., 0.], 100.), None);
+    }
+
+    #[test]
+    fn misses_beyond_max_dist() {
+        let mut objects = Objects::new();
+        cube_at(&mut objects, (5., 0., 0.), (0., 0.));
+
+        assert_eq!(raycast(&objects, [0., 0., 0.], [1., 0., 0.], 3.9), None);
+        let hit = raycast(&objects, [0., 0., 0.], [1., 0., 0.], 4.1).unwrap();
+        assert!((hit.distance - 4.).abs() < 1e-4);
+        // Direction needn't be normalised
+        assert_eq!(raycast(&objects, [0., 0., 0.], [10., 0., 0.], 4.1), Some(hit));
+
+        assert_eq!(raycast(&objects, [0., 0., 0.], [-1., 0., 0.], 100.), None);
+        assert_eq!(raycast(&objects, [0., 0., 0.], [0., 0., 0.], 100.), None);
+    }
+
+    #[test]
+    fn finds_the_nearest_of_two_objects() {
+        let mut objects = Objects::new();
+        let far = cube_at(&mut objects, (10., 0., 0.), (0., 0.));
+        let near = cube_at(&mut objects, (5., 0., 0.), (0., 0.));
+
+        let hit = raycast(&objects, [0., 0., 0.], [1., 0., 0.], 100.).unwrap();
+        assert_eq!(hit.object, near);
+        assert_near(hit.point, [4., 0., 0.]);
+
+        let hit = raycast(&objects, [15., 0., 0.], [-1., 0., 0.], 100.).unwrap();
+        assert_eq!(hit.object, far);
+        assert_near(hit.point, [11., 0., 0.]);
+    }
+
+    #[test]
+    fn normal_faces_the_ray() {
+        let mut objects = Objects::new();
+        cube_at(&mut objects, (5., 0., 0.), (0., 0.));
+
+        let hit = raycast(&objects, [0., 0., 0.], [1., 0., 0.], 100.).unwrap();
+        assert_near(hit.normal, [-1., 0., 0.]);
+        let hit = raycast(&objects, [10., 0., 0.], [-1., 0., 0.], 100.).unwrap();
+        assert_near(hit.normal, [1., 0., 0.]);
+        let hit = raycast(&objects, [5., 0., 0.], [0., -1., 0.], 100.).unwrap();
+        assert_near(hit.point, [5., -1., 0.]);
+        assert_near(hit.normal, [0., 1., 0.]);
+    }
+
+    #[test]
+    fn ray_box_and_triangle_tests() {
+        let aabb = Aabb {
+            min: [-1., -1., -1.],
+            max: [1., 1., 1.],
+        };
+        let dir = glm::vec3(1., 0., 0.);
+        assert!(ray_hits_aabb(&aabb, &glm::vec3(-5., 0., 0.), &dir, 4.5));
+        assert!(!ray_hits_aabb(&aabb, &glm::vec3(-5., 0., 0.), &dir, 3.5));
+        assert!(!ray_hits_aabb(&aabb, &glm::vec3(-5., 2., 0.), &dir, 100.));
+        assert!(ray_hits_aabb(&aabb, &glm::vec3(0., 0., 0.), &dir, 0.1));
+
+        let tri = (glm::vec3(2., -1., -1.), glm::vec3(2., 1., -1.), glm::vec3(2., 0., 1.));
+        assert_eq!(ray_hits_tri(&glm::vec3(0., 0., 0.), &dir, &tri), Some(2.));
+        assert_eq!(ray_hits_tri(&glm::vec3(4., 0., 0.), &-dir, &tri), Some(2.));
+        assert_eq!(ray_hits_tri(&glm::vec3(4., 0., 0.), &dir, &tri), None);
+        assert_eq!(ray_hits_tri(&glm::vec3(0., 0., 0.), &glm::vec3(0., 1., 0.), &tri), None);
+        assert_eq!(ray_hits_tri(&glm::vec3(0., 5., 0.), &dir, &tri), None);
+    }
+}
@@ -0,0 +1,212 @@
+//! Drawing the objects of a `Term3D` as seen by each of its cameras.
+
+use crate::bounds::Frustum;
+use crate::core::*;
+use crate::glm::{self, Vec2};
+use crate::raster::{Clip, Triangle};
+use crate::scene::{ObjectHandle, Objects};
+use crate::{Camera, Render, Term3D, PROJECTION_SCALE};
+
+use easycurses::{Color, ColorPair};
+use ordered_float::NotNan;
+
+use std::cmp::{max, min};
+
+/// What is printed in the cells covered by a triangle with a given id.
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) struct Fill {
+    c: char,
+    color: ColorPair,
+    /// The object and index of the face drawn, if picking is enabled.
+    pick: Option<(ObjectHandle, usize)>,
+}
+
+impl Term3D {
+    /// Draw the objects as seen by every camera, in increasing camera `order`.
+    pub(crate) fn render_scene(&mut self) {
+        let (w, h) = self.get_dimensions();
+        self.objects.update_world_transforms();
+
+        let mut tris = Vec::new();
+        self.fills.clear();
+
+        let mut cameras = self.cameras.iter().collect::<Vec<_>>();
+        cameras.sort_by_key(|cam| cam.order);
+        for cam in cameras {
+            queue_camera(&self.objects, cam, (w, h), self.picking, &mut self.fills, &mut tris);
+        }
+
+        self.framebuffer.resize(w, h);
+        self.framebuffer.clear();
+        self.framebuffer.draw(&tris, self.render_threads);
+
+        // Copy the rasterised faces onto the terminal
+        let mut current_color = None;
+        for y in 0..h {
+            for x in 0..w {
+                if let Some(i) = self.framebuffer.get(x, y) {
+                    let fill = self.fills[i as usize];
+                    if current_color != Some(fill.color) {
+                        self.backend.set_color_pair(fill.color);
+                        current_color = Some(fill.color);
+                    }
+                    draw_cell(&mut self.backend, fill.c, x, y);
+                }
+            }
+        }
+    }
+
+    /// The object and index of the face drawn in a cell in the last frame, if any.
+    /// Always `None` unless `picking` is enabled.
+    pub fn pick(&self, x: i32, y: i32) -> Option<(ObjectHandle, usize)> {
+        self.framebuffer
+            .get(x, y)
+            .and_then(|i| self.fills[i as usize].pick)
+    }
+}
+
+/// Add the triangles of every face visible to `cam` to `tris`, furthest first.
+fn queue_camera(
+    objects: &Objects,
+    cam: &Camera,
+    screen: (i32, i32),
+    picking: bool,
+    fills: &mut Vec<Fill>,
+    tris: &mut Vec<(Triangle, Clip)>,
+) {
+    // The part of the screen the camera draws to
+    let (left, top, right, bottom) = match cam.viewport {
+        Some(v) => (
+            max(0, v.x),
+            max(0, v.y),
+            min(screen.0, v.x + v.width),
+            min(screen.1, v.y + v.height),
+        ),
+        None => (0, 0, screen.0, screen.1),
+    };
+    let (w, h) = (right - left, bottom - top);
+    if w <= 0 || h <= 0 {
+        return;
+    }
+    let clip = (left, right - 1, top, bottom - 1);
+    let (cx, cy) = (left as f32 + w as f32 / 2., top as f32 + h as f32 / 2.);
+
+    // A viewport hides whatever earlier cameras drew beneath it
+    if cam.viewport.is_some() {
+        let id = fills.len() as u32;
+        fills.push(Fill {
+            c: ' ',
+            color: ColorPair::default(),
+            pick: None,
+        });
+        let (l, t, r, b) = (left as f32, top as f32, right as f32, bottom as f32);
+        let (tl, tr, br, bl) = (Vec2::new(l, t), Vec2::new(r, t), Vec2::new(r, b), Vec2::new(l, b));
+        tris.push((Triangle { verts: [tl, tr, br], id }, clip));
+        tris.push((Triangle { verts: [tl, br, bl], id }, clip));
+    }
+
+    let mut face_list = Vec::<([Vec2; 4], Option<Color>)>::new(); // All faces that will be rendered onto the screen
+    let mut face_ids = Vec::<(ObjectHandle, usize)>::new(); // Which object and face each of face_list is
+    let mut depth = Vec::<f32>::new(); // Face's distances from the camera
+
+    let frustum = Frustum::new(w, h, PROJECTION_SCALE);
+
+    // Vertices after mutation by camera position and rotation,
+    // and object transform.
+    let mut vert_list = Vec::<[f32; 3]>::new();
+    // Position of the vertices in vert_list as screen
+    // coordinates.
+    let mut screen_coords = Vec::<Vec2>::new();
+
+    for (handle, obj) in objects.iter() {
+        if !obj.visible || obj.layers & cam.layers == 0 {
+            continue;
+        }
+
+        let model = obj.world;
+
+        // Skip the object entirely if its bounding sphere is out of view
+        let sphere = obj.mesh.bounds().sphere;
+        let center = cam.view(transform_point(&model, sphere.center));
+        if !frustum.intersects_sphere(center, sphere.radius) {
+            continue;
+        }
+
+        // Each instance reuses the buffers of the last
+        vert_list.clear();
+        screen_coords.clear();
+        for &vert in &obj.mesh.verts {
+            let [mut x, mut y, z] = cam.view(transform_point(&model, vert));
+            vert_list.push([x, y, z]);
+
+            let f = PROJECTION_SCALE / z;
+            x *= f;
+            y *= f;
+            screen_coords.push(Vec2::new(cx + x, cy + y));
+        }
+
+        for i in 0..obj.mesh.faces.len() {
+            let face = obj.mesh.faces[i];
+
+            // Keep faces with a corner in front of the camera, whose screen
+            // bounding box overlaps the viewport
+            let mut in_front = false;
+            let mut top_left = screen_coords[face.0[0] as usize];
+            let mut bottom_right = top_left;
+            for &i in &face.0 {
+                let p = screen_coords[i as usize];
+                in_front |= vert_list[i as usize][2] > 0.;
+                top_left = glm::min2(&top_left, &p);
+                bottom_right = glm::max2(&bottom_right, &p);
+            }
+            let on_screen = in_front
+                && bottom_right.x >= left as f32
+                && bottom_right.y >= top as f32
+                && top_left.x <= right as f32
+                && top_left.y <= bottom as f32;
+
+            if on_screen {
+                face_list.push((
+                    [
+                        screen_coords[face.0[0] as usize],
+                        screen_coords[face.0[1] as usize],
+                        screen_coords[face.0[2] as usize],
+                        screen_coords[face.0[3] as usize],
+                    ],
+                    face.1,
+                ));
+                face_ids.push((handle, i));
+
+                // depth += [sum(sum(vert_list[j][k] for j in face)**2 for k in range(3))]
+                depth.push(
+                    (0..3)
+                        .map(|k| {
+                            face.0
+                                .iter()
+                                .map(|&j| vert_list[j as usize][k as usize])
+                                .sum::<f32>()
+                                .powi(2)
+                        })
+                        .sum::<f32>(),
+                );
+            }
+        }
+    }
+
+    let mut order = (0..face_list.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|&k| NotNan::new(depth[k]).unwrap());
+    order.reverse();
+
+    for i in order {
+        let id = fills.len() as u32;
+        fills.push(Fill {
+            c: '#',
+            color: ColorPair::new(face_list[i].1.unwrap_or(Color::White), Color::Black),
+            pick: if picking { Some(face_ids[i]) } else { None },
+        });
+
+        let [a, b, c, d] = face_list[i].0;
+        tris.push((Triangle { verts: [a, b, c], id }, clip));
+        tris.push((Triangle { verts: [a, c, d], id }, clip));
+    }
+}
@@ -0,0 +1,311 @@
+//! The objects in a `Term3D`, and the parent-child relationships between them.
+//!
+//! Objects are addressed by `ObjectHandle`s, which stay valid while other objects are
+//! spawned and despawned. An object with a parent has its transform applied relative
+//! to the parent, so it moves and turns along with it.
+
+use crate::core::transform_point;
+use crate::glm::Mat4;
+use crate::Object;
+
+use std::ops::{Index, IndexMut};
+
+/// Refers to an object spawned into `Objects`.
+///
+/// Once the object is despawned its slot may be reused, but the generation stored in
+/// the handle will no longer match, so an old handle never refers to a new object.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectHandle {
+    index: u32,
+    generation: u32,
+}
+
+struct Slot {
+    generation: u32,
+    object: Option<Object>,
+}
+
+pub struct Objects {
+    slots: Vec<Slot>,
+    /// Indices of the empty slots
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl Objects {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Add an object to the scene, returning the handle to refer to it by.
+    pub fn spawn(&mut self, obj: Object) -> ObjectHandle {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.object = Some(obj);
+                ObjectHandle {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    object: Some(obj),
+                });
+                ObjectHandle {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Remove an object from the scene along with all of its descendants, returning it.
+    /// Returns `None` if the handle is stale.
+    pub fn despawn(&mut self, handle: ObjectHandle) -> Option<Object> {
+        if !self.contains(handle) {
+            return None;
+        }
+
+        let children = self.children(handle).collect::<Vec<_>>();
+        for child in children {
+            self.despawn(child);
+        }
+
+        let slot = &mut self.slots[handle.index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.len -= 1;
+        let mut obj = slot.object.take()?;
+        // The parent's slot may be reused by the time this object is spawned again
+        obj.parent = None;
+        Some(obj)
+    }
+
+    pub fn contains(&self, handle: ObjectHandle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: ObjectHandle) -> Option<&Object> {
+        match self.slots.get(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation => slot.object.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: ObjectHandle) -> Option<&mut Object> {
+        match self.slots.get_mut(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation => slot.object.as_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectHandle, &Object)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            let handle = ObjectHandle {
+                index: i as u32,
+                generation: slot.generation,
+            };
+            slot.object.as_ref().map(|obj| (handle, obj))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ObjectHandle, &mut Object)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let handle = ObjectHandle {
+                index: i as u32,
+                generation: slot.generation,
+            };
+            slot.object.as_mut().map(|obj| (handle, obj))
+        })
+    }
+
+    /// The first object found with the given name.
+    pub fn find(&self, name: &str) -> Option<ObjectHandle> {
+        self.iter()
+            .find(|(_, obj)| obj.name.as_deref() == Some(name))
+            .map(|(handle, _)| handle)
+    }
+
+    /// Every object with the given tag.
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = ObjectHandle> + 'a {
+        self.iter()
+            .filter(move |(_, obj)| obj.tags.iter().any(|t| t == tag))
+            .map(|(handle, _)| handle)
+    }
+
+    /// Attach `child` to `parent`, or detach it with `None`. The child keeps its
+    /// transform, which is now relative to the new parent.
+    ///
+    /// Returns false and changes nothing if either handle is stale, or this would make
+    /// the object its own ancestor.
+    pub fn set_parent(&mut self, child: ObjectHandle, parent: Option<ObjectHandle>) -> bool {
+        let mut ancestor = parent;
+        while let Some(handle) = ancestor {
+            if handle == child {
+                return false;
+            }
+            match self.get(handle) {
+                Some(obj) => ancestor = obj.parent,
+                None => return false,
+            }
+        }
+
+        match self.get_mut(child) {
+            Some(obj) => {
+                obj.parent = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn parent(&self, child: ObjectHandle) -> Option<ObjectHandle> {
+        self.get(child).and_then(|obj| obj.parent)
+    }
+
+    /// The objects directly attached to `parent`.
+    pub fn children(&self, parent: ObjectHandle) -> impl Iterator<Item = ObjectHandle> + '_ {
+        self.iter()
+            .filter(move |(_, obj)| obj.parent == Some(parent))
+            .map(|(handle, _)| handle)
+    }
+
+    /// The matrix taking points from an object's space to world space, including the
+    /// transforms of all of its ancestors.
+    pub fn world_matrix(&self, handle: ObjectHandle) -> Option<Mat4> {
+        let obj = self.get(handle)?;
+        match obj.parent.filter(|&parent| self.contains(parent)) {
+            Some(parent) => Some(self.world_matrix(parent)? * obj.transform.matrix()),
+            None => Some(obj.transform.matrix()),
+        }
+    }
+
+    /// Where an object's origin is in world space.
+    pub fn world_position(&self, handle: ObjectHandle) -> Option<(f32, f32, f32)> {
+        let [x, y, z] = transform_point(&self.world_matrix(handle)?, [0., 0., 0.]);
+        Some((x, y, z))
+    }
+
+    /// Recalculate the cached world matrix of every object, working out each
+    /// ancestor's only once.
+    pub(crate) fn update_world_transforms(&mut self) {
+        let mut done = vec![false; self.slots.len()];
+        let mut chain = Vec::new();
+
+        for i in 0..self.slots.len() {
+            // Climb to the nearest ancestor which is already done, then work back down
+            let mut next = self.slots[i].object.as_ref().map(|_| i);
+            while let Some(j) = next {
+                if done[j] {
+                    break;
+                }
+                chain.push(j);
+                next = self.live_parent(j);
+            }
+
+            while let Some(j) = chain.pop() {
+                let parent_world = self
+                    .live_parent(j)
+                    .map(|p| self.slots[p].object.as_ref().unwrap().world);
+                let obj = self.slots[j].object.as_mut().unwrap();
+                let local = obj.transform.matrix();
+                obj.world = match parent_world {
+                    Some(parent_world) => parent_world * local,
+                    None => local,
+                };
+                done[j] = true;
+            }
+        }
+    }
+
+    /// The slot of the parent of the object in slot `index`, if it has one which
+    /// hasn't been despawned.
+    fn live_parent(&self, index: usize) -> Option<usize> {
+        let parent = self.slots[index].object.as_ref()?.parent?;
+        self.get(parent).map(|_| parent.index as usize)
+    }
+}
+
+impl Default for Objects {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<ObjectHandle> for Objects {
+    type Output = Object;
+
+    /// Panics if the handle is stale.
+    fn index(&self, handle: ObjectHandle) -> &Object {
+        self.get(handle).expect("stale object handle")
+    }
+}
+
+impl IndexMut<ObjectHandle> for Objects {
+    /// Panics if the handle is stale.
+    fn index_mut(&mut self, handle: ObjectHandle) -> &mut Object {
+        self.get_mut(handle).expect("stale object handle")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mesh;
+
+    #[test]
+    fn respawned_child_forgets_its_parent() {
+        let mut objects = Objects::new();
+        let parent = objects.spawn(Object::new(Mesh::cube()));
+        let child = objects.spawn(Object::new(Mesh::cube()));
+        objects[parent].transform.pos = (5., 0., 0.);
+        assert!(objects.set_parent(child, Some(parent)));
+
+        let child = objects.despawn(child).unwrap();
+        objects.despawn(parent);
+        // Lands in the parent's old slot
+        let child = objects.spawn(child);
+
+        assert_eq!(objects.parent(child), None);
+        assert_eq!(objects.world_position(child), Some((0., 0., 0.)));
+        objects.update_world_transforms();
+        assert_eq!(objects[child].world, objects.world_matrix(child).unwrap());
+    }
+
+    #[test]
+    fn stale_parent_is_not_followed_into_a_reused_slot() {
+        let mut objects = Objects::new();
+        let parent = objects.spawn(Object::new(Mesh::cube()));
+        let child = objects.spawn(Object::new(Mesh::cube()));
+        assert!(objects.set_parent(child, Some(parent)));
+        let mut child = objects.despawn(child).unwrap();
+        // As if the object had been taken out before the parent went
+        child.parent = Some(parent);
+        objects.despawn(parent);
+
+        let mut other = Object::new(Mesh::cube());
+        other.transform.pos = (5., 0., 0.);
+        let other = objects.spawn(other);
+        let child = objects.spawn(child);
+        assert_ne!(objects.parent(child), Some(other));
+
+        objects.update_world_transforms();
+        assert_eq!(objects[child].world, objects.world_matrix(child).unwrap());
+        assert_eq!(objects.world_position(child), Some((0., 0., 0.)));
+    }
+}
@@ -0,0 +1,146 @@
+//! Putting the terminal back the way it was when a game panics or is interrupted, and
+//! redrawing it after being suspended.
+//!
+//! Signal handlers can't safely do much, so they only raise flags here, which
+//! `Term3D::poll_input` acts on at the start of the next frame. The exception is a
+//! second interrupt, which kills the game after putting the terminal back with the
+//! little that's safe to do from a handler.
+
+use std::io::{self, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+/// Turns off mouse and focus reporting.
+const RESET_MODES: &[u8] = b"\x1b[?1003l\x1b[?1006l\x1b[?1004l";
+/// Turns off kitty's keyboard protocol.
+const RESET_KITTY: &[u8] = b"\x1b[<u";
+/// What curses would do to the screen when leaving: reset colors, show the cursor and
+/// leave the alternate screen.
+#[cfg(unix)]
+const RESET_SCREEN: &[u8] = b"\x1b[0m\x1b[?25h\x1b[?1049l";
+
+/// Whether kitty's keyboard protocol is on, so the panic hook knows to turn it off.
+pub(crate) static KITTY_KEYBOARD: AtomicBool = AtomicBool::new(false);
+/// Set on SIGINT or SIGTERM.
+pub(crate) static QUIT: AtomicBool = AtomicBool::new(false);
+/// Set on SIGTSTP, when Ctrl-Z is pressed.
+pub(crate) static SUSPEND: AtomicBool = AtomicBool::new(false);
+/// Set on SIGCONT, when the process carries on after being stopped.
+pub(crate) static RESUMED: AtomicBool = AtomicBool::new(false);
+
+/// The terminal's settings from before curses changed them, once saved.
+#[cfg(unix)]
+static mut SAVED_TERMIOS: std::mem::MaybeUninit<libc::termios> = std::mem::MaybeUninit::uninit();
+#[cfg(unix)]
+static TERMIOS_SAVED: AtomicBool = AtomicBool::new(false);
+
+/// Clear a flag, returning whether it was set.
+pub(crate) fn take(flag: &AtomicBool) -> bool {
+    flag.swap(false, Ordering::SeqCst)
+}
+
+/// Install the panic hook and signal handlers. Only the first call does anything.
+pub(crate) fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        // Leave curses before the panic message is printed, so it isn't lost
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+
+        #[cfg(unix)]
+        unsafe {
+            // Curses installs its own handlers for some of these, so ours replace them
+            libc::signal(libc::SIGINT, on_quit as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, on_quit as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            libc::signal(libc::SIGTSTP, on_suspend as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            libc::signal(libc::SIGCONT, on_resume as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    });
+}
+
+/// Save the terminal's settings, for a signal handler to put back if the game has to be
+/// killed. Call before curses starts. Only the first call does anything.
+#[cfg(unix)]
+pub(crate) fn save_terminal() {
+    static SAVE: Once = Once::new();
+    SAVE.call_once(|| unsafe {
+        let saved = std::ptr::addr_of_mut!(SAVED_TERMIOS) as *mut libc::termios;
+        if libc::tcgetattr(libc::STDIN_FILENO, saved) == 0 {
+            TERMIOS_SAVED.store(true, Ordering::SeqCst);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub(crate) fn save_terminal() {}
+
+/// Turn off the modes term3d turns on and leave curses.
+pub(crate) fn restore_terminal() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(RESET_MODES);
+    if KITTY_KEYBOARD.swap(false, Ordering::SeqCst) {
+        let _ = stdout.write_all(RESET_KITTY);
+    }
+    let _ = stdout.flush();
+    pancurses::endwin();
+}
+
+/// Put the terminal back as well as can be done from a signal handler, where neither
+/// Rust's stdout nor curses can be used: only `write` and `tcsetattr` are safe there.
+#[cfg(unix)]
+fn restore_terminal_from_handler() {
+    let write = |bytes: &[u8]| unsafe {
+        libc::write(libc::STDOUT_FILENO, bytes.as_ptr() as *const libc::c_void, bytes.len());
+    };
+    write(RESET_MODES);
+    if KITTY_KEYBOARD.swap(false, Ordering::SeqCst) {
+        write(RESET_KITTY);
+    }
+    write(RESET_SCREEN);
+
+    if TERMIOS_SAVED.load(Ordering::SeqCst) {
+        unsafe {
+            let saved = std::ptr::addr_of!(SAVED_TERMIOS) as *const libc::termios;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+        }
+    }
+}
+
+/// Stop the process, as Ctrl-Z would have without our handler, returning once it's
+/// carried on.
+#[cfg(unix)]
+pub(crate) fn stop() {
+    unsafe {
+        libc::raise(libc::SIGSTOP);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn stop() {}
+
+#[cfg(unix)]
+extern "C" fn on_quit(signal: libc::c_int) {
+    // A game stuck in a long frame never gets to quit, so a second interrupt before
+    // the first was seen to kills it outright
+    if QUIT.swap(true, Ordering::SeqCst) {
+        restore_terminal_from_handler();
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_suspend(_: libc::c_int) {
+    SUSPEND.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn on_resume(_: libc::c_int) {
+    RESUMED.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,355 @@
+//! A stack of game states, for flows like title screen → gameplay → pause menu.
+//!
+//! Only the state on top of the stack is updated and handles input. Each state has
+//! its own objects and cameras, swapped into `Term3D` while it's active, unless it
+//! shares the scene of the state underneath, as a pause menu would. A `StateStack`
+//! is itself a `Game`, so it's run like any other:
+//!
+//! ```ignore
+//! let mut game = StateStack::new(Box::new(TitleScreen::new()));
+//! term.run(&mut game);
+//! ```
+
+use crate::{Camera, Game, Render, Term3D};
+use crate::scene::Objects;
+
+use easycurses::{Color, ColorPair};
+
+use std::mem;
+
+/// What a state's `update` wants to happen to the stack.
+pub enum Transition {
+    /// Stay as things are.
+    None,
+    /// Put a state on top of this one, pausing this one.
+    Push(Box<dyn State>),
+    /// Remove this state, resuming the one underneath. Popping the last state quits.
+    Pop,
+    /// Remove this state and put another in its place.
+    Replace(Box<dyn State>),
+    /// Stop the game.
+    Quit,
+    /// Fade the screen out over half of the given seconds, make the transition, then
+    /// fade back in over the other half.
+    Fade(f32, Box<Transition>),
+}
+
+impl Transition {
+    /// This transition, made halfway through a fade lasting `seconds`.
+    pub fn faded(self, seconds: f32) -> Self {
+        Transition::Fade(seconds, Box::new(self))
+    }
+}
+
+pub trait State {
+    /// Called when the state is put on the stack, with its scene active.
+    fn start(&mut self, _term: &mut Term3D) {}
+    /// Called once a frame while the state is on top of the stack.
+    fn update(&mut self, term: &mut Term3D, delta: f32) -> Transition;
+    /// Called like `Game::fixed_update`, while the state is on top of the stack.
+    fn fixed_update(&mut self, _term: &mut Term3D, _step: f32) {}
+    /// Called once a frame instead of `update` while the state is underneath one which
+    /// lets it keep running; see `State::updates_below`.
+    fn background_update(&mut self, _term: &mut Term3D, _delta: f32) {}
+    /// Called once the scene has been drawn, while the state is on top of the stack or
+    /// visible underneath it; see `State::draws_below`.
+    fn draw_overlay(&mut self, _term: &mut Term3D) {}
+    /// Called after `update`, just before the scene is drawn, while the state is on top
+    /// of the stack.
+    fn pre_render(&mut self, _term: &mut Term3D) {}
+    /// Called on every state on the stack when the terminal is resized, with its new
+    /// width and height in cells.
+    fn on_resize(&mut self, _term: &mut Term3D, _width: i32, _height: i32) {}
+    /// Called on every state on the stack when the terminal gains or loses focus, in
+    /// terminals which report it.
+    fn on_focus_change(&mut self, _term: &mut Term3D, _focused: bool) {}
+    /// Called when another state is pushed on top of this one.
+    fn pause(&mut self, _term: &mut Term3D) {}
+    /// Called when this state is back on top of the stack.
+    fn resume(&mut self, _term: &mut Term3D) {}
+    /// Called when the state is taken off the stack, with its scene still active.
+    fn stop(&mut self, _term: &mut Term3D) {}
+
+    /// Use the objects and cameras of the state underneath rather than a scene of its
+    /// own, for overlays like pause menus.
+    fn shares_scene(&self) -> bool {
+        false
+    }
+    /// Keep calling `background_update` on the states underneath while this one is on
+    /// top.
+    fn updates_below(&self) -> bool {
+        false
+    }
+    /// Keep calling `draw_overlay` on the states underneath while this one is on top,
+    /// so HUDs stay visible behind a menu.
+    fn draws_below(&self) -> bool {
+        false
+    }
+}
+
+/// The objects and cameras belonging to a state.
+struct Scene {
+    objects: Objects,
+    cameras: Vec<Camera>,
+}
+
+impl Scene {
+    /// An empty scene, set up like a new `Term3D`'s.
+    fn new() -> Self {
+        Self {
+            objects: Objects::new(),
+            cameras: vec![Camera::new((0., 0., 0.), (0., 0.))],
+        }
+    }
+
+    /// Trade contents with the scene in `term`.
+    fn swap(&mut self, term: &mut Term3D) {
+        mem::swap(&mut self.objects, &mut term.objects);
+        mem::swap(&mut self.cameras, &mut term.cameras);
+    }
+}
+
+struct Entry {
+    state: Box<dyn State>,
+    /// The state's own scene while it isn't active, or whatever it replaced in `term`
+    /// while it is. `None` if it shares the scene underneath.
+    scene: Option<Scene>,
+}
+
+struct Fade {
+    time: f32,
+    duration: f32,
+    /// The transition to make halfway through, once the screen is dark.
+    transition: Option<Transition>,
+}
+
+pub struct StateStack {
+    entries: Vec<Entry>,
+    /// The entry whose scene is in `term`, or `None` if it has its original scene.
+    active: Option<usize>,
+    fade: Option<Fade>,
+    /// The state to start with, until `Game::start` pushes it.
+    initial: Option<Box<dyn State>>,
+}
+
+impl StateStack {
+    pub fn new(initial: Box<dyn State>) -> Self {
+        Self {
+            entries: Vec::new(),
+            active: None,
+            fade: None,
+            initial: Some(initial),
+        }
+    }
+
+    /// How many states are on the stack.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether the screen is fading between states. States aren't updated meanwhile.
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    /// Carry out a transition asked for by the state on top.
+    pub fn apply(&mut self, term: &mut Term3D, transition: Transition) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(state) => {
+                if let Some(top) = self.entries.last_mut() {
+                    top.state.pause(term);
+                }
+                self.push(term, state);
+            }
+            Transition::Pop => {
+                self.pop(term);
+                match self.entries.last_mut() {
+                    Some(top) => top.state.resume(term),
+                    None => term.quit(),
+                }
+            }
+            Transition::Replace(state) => {
+                self.pop(term);
+                self.push(term, state);
+            }
+            Transition::Quit => term.quit(),
+            Transition::Fade(duration, transition) => {
+                self.fade = Some(Fade {
+                    time: 0.,
+                    duration,
+                    transition: Some(*transition),
+                });
+            }
+        }
+    }
+
+    fn push(&mut self, term: &mut Term3D, state: Box<dyn State>) {
+        let scene = if state.shares_scene() && !self.entries.is_empty() {
+            None
+        } else {
+            Some(Scene::new())
+        };
+        self.entries.push(Entry { state, scene });
+
+        let top = self.entries.len() - 1;
+        self.activate(term, self.owner(top));
+        self.entries[top].state.start(term);
+    }
+
+    fn pop(&mut self, term: &mut Term3D) {
+        let top = match self.entries.len() {
+            0 => return,
+            len => len - 1,
+        };
+        self.activate(term, self.owner(top));
+        self.entries[top].state.stop(term);
+
+        // Put back whatever the state's scene replaced before dropping it
+        if self.active == Some(top) {
+            self.activate(term, None);
+        }
+        self.entries.pop();
+        if let Some(top) = self.entries.len().checked_sub(1) {
+            self.activate(term, self.owner(top));
+        }
+    }
+
+    /// The entry whose scene the state at `index` uses.
+    fn owner(&self, index: usize) -> Option<usize> {
+        (0..=index).rev().find(|&i| self.entries[i].scene.is_some())
+    }
+
+    /// Put the scene of the entry `owner` into `term`, putting back the active one.
+    fn activate(&mut self, term: &mut Term3D, owner: Option<usize>) {
+        if owner == self.active {
+            return;
+        }
+        if let Some(scene) = self.active.and_then(|i| self.entries[i].scene.as_mut()) {
+            scene.swap(term);
+        }
+        if let Some(scene) = owner.and_then(|i| self.entries[i].scene.as_mut()) {
+            scene.swap(term);
+        }
+        self.active = owner;
+    }
+
+    /// Call `f` on each state from `lowest` up to the top of the stack, with the state's
+    /// scene active. The top state's scene is left active.
+    fn each_from(
+        &mut self,
+        term: &mut Term3D,
+        lowest: usize,
+        mut f: impl FnMut(&mut dyn State, &mut Term3D),
+    ) {
+        for i in lowest..self.entries.len() {
+            self.activate(term, self.owner(i));
+            f(&mut *self.entries[i].state, term);
+        }
+    }
+
+    /// The lowest state which `flag` carries down to from the top of the stack.
+    fn lowest(&self, flag: impl Fn(&dyn State) -> bool) -> usize {
+        let mut lowest = self.entries.len().saturating_sub(1);
+        while lowest > 0 && flag(&*self.entries[lowest].state) {
+            lowest -= 1;
+        }
+        lowest
+    }
+
+    /// Darken the screen by `amount` from 0 to 1, with an ordered dither.
+    fn draw_fade(term: &mut Term3D, amount: f32) {
+        const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+        let (w, h) = term.get_dimensions();
+        term.set_color(ColorPair::new(Color::Black, Color::Black));
+        for y in 0..h {
+            for x in 0..w {
+                let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.;
+                if threshold < amount {
+                    term.draw(' ', x, y);
+                }
+            }
+        }
+    }
+}
+
+impl Game for StateStack {
+    fn start(&mut self, term: &mut Term3D) {
+        if let Some(state) = self.initial.take() {
+            self.push(term, state);
+        }
+    }
+
+    fn update(&mut self, term: &mut Term3D, delta: f32) {
+        if let Some(fade) = &mut self.fade {
+            fade.time += delta;
+            if fade.time >= fade.duration / 2. {
+                if let Some(transition) = fade.transition.take() {
+                    self.apply(term, transition);
+                }
+            }
+            if self.fade.as_ref().is_some_and(|fade| fade.time >= fade.duration) {
+                self.fade = None;
+            }
+            return;
+        }
+
+        let top = match self.entries.len() {
+            0 => return term.quit(),
+            len => len - 1,
+        };
+        for i in self.lowest(|state| state.updates_below())..top {
+            self.activate(term, self.owner(i));
+            self.entries[i].state.background_update(term, delta);
+        }
+        self.activate(term, self.owner(top));
+
+        let transition = self.entries[top].state.update(term, delta);
+        self.apply(term, transition);
+    }
+
+    fn fixed_update(&mut self, term: &mut Term3D, step: f32) {
+        if self.fade.is_none() {
+            let top = self.entries.len().saturating_sub(1);
+            self.each_from(term, top, |state, term| state.fixed_update(term, step));
+        }
+    }
+
+    fn pre_render(&mut self, term: &mut Term3D) {
+        if self.fade.is_none() {
+            let top = self.entries.len().saturating_sub(1);
+            self.each_from(term, top, |state, term| state.pre_render(term));
+        }
+    }
+
+    fn draw_overlay(&mut self, term: &mut Term3D) {
+        let lowest = self.lowest(|state| state.draws_below());
+        self.each_from(term, lowest, |state, term| state.draw_overlay(term));
+
+        if let Some(fade) = &self.fade {
+            // Darkest halfway through
+            let half = (fade.duration / 2.).max(f32::EPSILON);
+            let amount = 1. - ((fade.time - half) / half).abs();
+            Self::draw_fade(term, amount.clamp(0., 1.));
+        }
+    }
+
+    fn on_resize(&mut self, term: &mut Term3D, width: i32, height: i32) {
+        self.each_from(term, 0, |state, term| state.on_resize(term, width, height));
+    }
+
+    fn on_focus_change(&mut self, term: &mut Term3D, focused: bool) {
+        self.each_from(term, 0, |state, term| state.on_focus_change(term, focused));
+    }
+
+    fn on_exit(&mut self, term: &mut Term3D) {
+        while !self.entries.is_empty() {
+            self.pop(term);
+        }
+    }
+}